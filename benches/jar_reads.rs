@@ -0,0 +1,109 @@
+//! Measures the throughput of concurrent reads from a `JarAssetSource`.
+//!
+//! Run with `cargo bench --bench jar_reads [-- <path to a jar>]`. Without a path, a synthetic
+//! archive of compressible texture-sized entries is generated in the temporary directory.
+//! The results are compared with reads through a single archive behind a mutex.
+
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use vakfu::assets::JarAssetSource;
+use zip::ZipArchive;
+use zip::write::{SimpleFileOptions, ZipWriter};
+
+const SYNTHETIC_ENTRIES: usize = 1024;
+const SYNTHETIC_ENTRY_SIZE: usize = 64 * 1024;
+const ROUNDS: usize = 4;
+const THREAD_COUNTS: [usize; 5] = [1, 2, 4, 8, 16];
+
+fn main() -> anyhow::Result<()> {
+    let path = match std::env::args().skip(1).find(|arg| !arg.starts_with("--")) {
+        Some(path) => PathBuf::from(path),
+        None => write_synthetic_archive()?,
+    };
+
+    let source = JarAssetSource::new(&path)?;
+    let locked = Mutex::new(ZipArchive::new(BufReader::new(File::open(&path)?))?);
+    let names: Vec<String> = locked
+        .lock()
+        .unwrap()
+        .file_names()
+        .map(str::to_owned)
+        .collect();
+    println!("{} entries in {}", names.len(), path.display());
+
+    for threads in THREAD_COUNTS {
+        let (bytes, elapsed) = run(&names, threads, |name| source.read_entry(name).unwrap());
+        report("JarAssetSource", threads, bytes, elapsed);
+
+        let (bytes, elapsed) = run(&names, threads, |name| {
+            let mut archive = locked.lock().unwrap();
+            let mut entry = archive.by_name(name).unwrap();
+            let mut buf = Vec::with_capacity(entry.size() as usize);
+            entry.read_to_end(&mut buf).unwrap();
+            buf
+        });
+        report("Mutex<ZipArchive>", threads, bytes, elapsed);
+    }
+
+    Ok(())
+}
+
+/// Reads every entry `ROUNDS` times, split between `threads` threads, and returns the number
+/// of bytes read along with the time it took.
+fn run(
+    names: &[String],
+    threads: usize,
+    read: impl Fn(&str) -> Vec<u8> + Sync,
+) -> (usize, Duration) {
+    let start = Instant::now();
+    let bytes = thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads)
+            .map(|i| {
+                let read = &read;
+                scope.spawn(move || {
+                    let mut bytes = 0;
+                    for _ in 0..ROUNDS {
+                        for name in names.iter().skip(i).step_by(threads) {
+                            bytes += read(name).len();
+                        }
+                    }
+                    bytes
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .sum()
+    });
+    (bytes, start.elapsed())
+}
+
+fn report(name: &str, threads: usize, bytes: usize, elapsed: Duration) {
+    let throughput = bytes as f64 / elapsed.as_secs_f64() / (1024. * 1024.);
+    println!("{name:>18} {threads:>2} threads: {throughput:>9.1} MiB/s ({elapsed:.2?})");
+}
+
+/// Writes an archive of deflated entries resembling textures, with runs of repeated pixels.
+fn write_synthetic_archive() -> anyhow::Result<PathBuf> {
+    let path = std::env::temp_dir().join("vakfu-jar-reads.jar");
+    let mut writer = ZipWriter::new(File::create(&path)?);
+    let mut state = 0x2545_f491_u32;
+    let mut data = vec![0; SYNTHETIC_ENTRY_SIZE];
+    for i in 0..SYNTHETIC_ENTRIES {
+        for run in data.chunks_mut(16) {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            run.fill((state >> 24) as u8);
+        }
+        writer.start_file(format!("gfx/{i}.tgam"), SimpleFileOptions::default())?;
+        writer.write_all(&data)?;
+    }
+    writer.finish()?;
+
+    Ok(path)
+}
@@ -0,0 +1,136 @@
+use std::fs::File;
+use std::path::PathBuf;
+
+use anyhow::bail;
+use pico_args::Arguments;
+use serde::Serialize;
+use vakfu::assets::{Animation, Frames, MapSpriteDefinition};
+use vakfu::raster::{self, RgbaImage, TextureArchive};
+
+use crate::cli;
+
+/// Exports the animation of a sprite definition as an animated PNG, or as a horizontal sheet
+/// of frames with the timings in a JSON file next to it when `--sheet` is given.
+pub fn export_animation(pargs: &mut Arguments) -> anyhow::Result<()> {
+    let maps = cli::game_maps(pargs)?;
+    let definition_id: i32 = pargs.value_from_str("--element")?;
+    let out: PathBuf = pargs.value_from_str("--out")?;
+    let sheet = pargs.contains("--sheet");
+    cli::finish(pargs)?;
+
+    let sprites = maps.load_library()?;
+    let Some(definition) = sprites.get(definition_id) else {
+        bail!("element {definition_id} does not exist");
+    };
+    let Animation::Frames(frames) = definition.animation() else {
+        bail!("element {definition_id} is not animated");
+    };
+    let mut textures = TextureArchive::open(maps.textures_path())?;
+    let Some(texture) = textures.get(definition.texture_id())? else {
+        bail!(
+            "texture {} of element {definition_id} does not exist",
+            definition.texture_id()
+        );
+    };
+    let images = slice_frames(definition, &frames, texture);
+
+    if sheet {
+        let json = out.with_extension("json");
+        write_sheet(&images, File::create(&out)?)?;
+        let metadata = SheetMetadata::new(definition_id, &frames, &images);
+        serde_json::to_writer_pretty(File::create(&json)?, &metadata)?;
+        println!(
+            "exported {} frames to {} and {}",
+            images.len(),
+            out.display(),
+            json.display()
+        );
+    } else {
+        raster::write_apng(&images, File::create(&out)?)?;
+        println!("exported {} frames to {}", images.len(), out.display());
+    }
+    Ok(())
+}
+
+/// Cuts the frames out of the texture of a sprite, paired with their durations.
+fn slice_frames(
+    definition: &MapSpriteDefinition,
+    frames: &Frames,
+    texture: &RgbaImage,
+) -> Vec<(RgbaImage, u16)> {
+    // the frame coordinates are relative to the atlas layout, which may differ from the texture
+    let (layout_width, layout_height) = definition.texture_size();
+    let scale_x = texture.width() as f32 / f32::from(layout_width.max(1));
+    let scale_y = texture.height() as f32 / f32::from(layout_height.max(1));
+    let width = (f32::from(frames.width()) * scale_x).round() as u32;
+    let height = (f32::from(frames.height()) * scale_y).round() as u32;
+
+    frames
+        .iter()
+        .zip(frames.frame_durations())
+        .map(|(frame, &duration)| {
+            let x = (f32::from(frame.x) * scale_x).round() as u32;
+            let y = (f32::from(frame.y) * scale_y).round() as u32;
+            let mut image = texture.crop(x, y, width, height);
+            image.unpremultiply();
+            if definition.flags().is_flip() {
+                image.flip_x();
+            }
+            (image, duration)
+        })
+        .collect()
+}
+
+/// Lays out the frames next to each other from left to right.
+fn write_sheet(images: &[(RgbaImage, u16)], output: File) -> anyhow::Result<()> {
+    let (width, height) = images
+        .first()
+        .map_or((0, 0), |(image, _)| (image.width(), image.height()));
+    let mut sheet = RgbaImage::new(width * images.len() as u32, height);
+    for (i, (image, _)) in images.iter().enumerate() {
+        sheet.blit(image, i as u32 * width, 0);
+    }
+    sheet.write_png(output)?;
+    Ok(())
+}
+
+/// Describes the frames of a sheet written by [`write_sheet`], in pixels of the sheet.
+#[derive(Debug, Serialize)]
+struct SheetMetadata {
+    element: i32,
+    frame_width: u32,
+    frame_height: u32,
+    total_time: u32,
+    frames: Vec<SheetFrame>,
+}
+
+#[derive(Debug, Serialize)]
+struct SheetFrame {
+    index: usize,
+    start: u16,
+    duration: u16,
+}
+
+impl SheetMetadata {
+    fn new(definition_id: i32, frames: &Frames, images: &[(RgbaImage, u16)]) -> Self {
+        let (frame_width, frame_height) = images
+            .first()
+            .map_or((0, 0), |(image, _)| (image.width(), image.height()));
+        Self {
+            element: definition_id,
+            frame_width,
+            frame_height,
+            total_time: frames.total_time(),
+            frames: frames
+                .iter()
+                .zip(frames.frame_durations())
+                .enumerate()
+                .map(|(index, (frame, &duration))| SheetFrame {
+                    index,
+                    start: frame.time,
+                    duration,
+                })
+                .collect(),
+        }
+    }
+}
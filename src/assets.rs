@@ -1,143 +1,155 @@
+mod format;
 mod light;
+#[cfg(feature = "bevy")]
+mod loader;
 mod map;
 mod sprite;
 mod tgam;
 
-use std::fs::File;
-use std::io::{BufReader, Read};
-use std::path::Path;
-use std::sync::{Arc, Mutex};
+pub use format::{FormatVersion, UnknownFormatVersion};
+pub use light::{DaylightGradient, DaylightKey, LightCell, LightDef, LightMap, OutdoorLight};
+#[cfg(feature = "bevy")]
+pub use loader::{JarAssetSource, TgamLoader};
+pub use map::{
+    CHUNK_SIZE, Color, Group, Map, MapArchive, MapChunk, MapElementDetails, Rgb, Rgba,
+    iso_to_screen, parse_chunk_name,
+};
+pub use sprite::{Animation, Frame, Frames, MapSpriteDefinition, MapSpriteLibrary, SpriteFlags};
+pub use tgam::{Tgam, TgamBuf};
+
+use std::fmt;
 
-use bevy::asset::io::{ErasedAssetReader, VecReader};
-use bevy::asset::{AssetLoader, RenderAssetUsages};
-use bevy::image::Image;
-use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
 use byte::TryRead;
 use byte::ctx::LittleEndian;
-pub use light::{LightCell, LightDef, LightMap};
-pub use map::{Map, MapChunk, MapElementDetails, Rgba};
-pub use sprite::{Animation, Frame, Frames, MapSpriteDefinition, MapSpriteLibrary};
-pub use tgam::Tgam;
 use thiserror::Error;
 
-#[derive(Debug, Default)]
-pub struct TgamLoader;
-
-impl AssetLoader for TgamLoader {
-    type Asset = Image;
-    type Error = AssetError;
-    type Settings = ();
+use crate::util;
 
-    async fn load(
-        &self,
-        reader: &mut dyn bevy::asset::io::Reader,
-        _settings: &Self::Settings,
-        _load_context: &mut bevy::asset::LoadContext<'_>,
-    ) -> Result<Self::Asset, Self::Error> {
-        let mut bytes = Vec::new();
-        reader.read_to_end(&mut bytes).await?;
-
-        let (tgam, _) = Tgam::try_read(&bytes, LittleEndian)?;
-        let extent = Extent3d {
-            width: tgam.width(),
-            height: tgam.height(),
-            depth_or_array_layers: 1,
-        };
-        let img = Image::new(
-            extent,
-            TextureDimension::D2,
-            tgam.rgba().to_vec(),
-            TextureFormat::Rgba8Unorm,
-            RenderAssetUsages::RENDER_WORLD,
-        );
-        Ok(img)
-    }
-
-    fn extensions(&self) -> &[&str] {
-        &["tgam"]
-    }
+#[derive(Debug, Error)]
+pub enum AssetError {
+    #[error("i/o error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("archive error: {0}")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("could not decode {}: {0}", .0.entry())]
+    Decoding(#[from] DecodeError),
+    #[error("encoding error: {0}")]
+    Encoding(byte::Error),
+    #[error("png encoding error: {0}")]
+    PngEncoding(#[from] png::EncodingError),
+    #[error("png decoding error: {0}")]
+    PngDecoding(#[from] png::DecodingError),
 }
 
-#[derive(Debug, Clone)]
-pub struct JarAssetSource {
-    archive: Arc<Mutex<zip::ZipArchive<BufReader<File>>>>,
+/// A failure to decode an archive entry, along with the values that were being read when it
+/// occurred.
+#[derive(Debug, Error)]
+pub struct DecodeError {
+    entry: String,
+    path: Vec<DecodeStep>,
+    #[source]
+    source: byte::Error,
 }
 
-impl JarAssetSource {
-    pub fn new(path: impl AsRef<Path>) -> Result<Self, AssetError> {
-        let file = File::open(path)?;
-        let archive = Arc::new(Mutex::new(zip::ZipArchive::new(BufReader::new(file))?));
-        Ok(Self { archive })
+impl DecodeError {
+    /// Returns the name of the archive entry that failed to decode.
+    pub fn entry(&self) -> &str {
+        &self.entry
     }
-}
 
-impl ErasedAssetReader for JarAssetSource {
-    fn read<'a>(
-        &'a self,
-        path: &'a std::path::Path,
-    ) -> bevy::tasks::BoxedFuture<
-        'a,
-        Result<Box<dyn bevy::asset::io::Reader + 'a>, bevy::asset::io::AssetReaderError>,
-    > {
-        Box::pin(async {
-            let mut archive = self.archive.lock().unwrap();
-            let mut entry = archive
-                .by_name(&path.to_string_lossy())
-                .map_err(|err| match err {
-                    zip::result::ZipError::Io(error) => error,
-                    zip::result::ZipError::FileNotFound => std::io::Error::new(
-                        std::io::ErrorKind::NotFound,
-                        format!("'{}' not found ({err})", path.display()),
-                    ),
-                    _ => std::io::Error::other(err),
-                })?;
-            let mut bytes = Vec::new();
-            entry.read_to_end(&mut bytes)?;
-            Ok(Box::new(VecReader::new(bytes)) as Box<dyn bevy::asset::io::Reader>)
-        })
+    /// Returns the values that were being read, from the entry itself to the innermost one.
+    pub fn path(&self) -> &[DecodeStep] {
+        &self.path
     }
 
-    fn read_meta<'a>(
-        &'a self,
-        _path: &'a std::path::Path,
-    ) -> bevy::tasks::BoxedFuture<
-        'a,
-        Result<Box<dyn bevy::asset::io::Reader + 'a>, bevy::asset::io::AssetReaderError>,
-    > {
-        unimplemented!()
+    /// Returns the innermost value that was being read.
+    pub fn innermost(&self) -> Option<&DecodeStep> {
+        self.path.last()
     }
 
-    fn read_directory<'a>(
-        &'a self,
-        _path: &'a std::path::Path,
-    ) -> bevy::tasks::BoxedFuture<
-        'a,
-        Result<Box<bevy::asset::io::PathStream>, bevy::asset::io::AssetReaderError>,
-    > {
-        unimplemented!()
+    /// Returns the underlying error.
+    pub fn error(&self) -> &byte::Error {
+        &self.source
     }
+}
 
-    fn is_directory<'a>(
-        &'a self,
-        _path: &'a std::path::Path,
-    ) -> bevy::tasks::BoxedFuture<'a, Result<bool, bevy::asset::io::AssetReaderError>> {
-        unimplemented!()
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, step) in self.path.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" > ")?;
+            }
+            write!(f, "{step}")?;
+        }
+        if let Some(step) = self.innermost() {
+            write!(f, " at byte {}: ", step.offset)?;
+        }
+        match self.source {
+            byte::Error::Incomplete => f.write_str("unexpected end of data"),
+            byte::Error::BadOffset(offset) => write!(f, "bad offset {offset}"),
+            byte::Error::BadInput { err } => write!(f, "invalid data: {err}"),
+        }
     }
+}
+
+/// A value that was being read when decoding failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodeStep {
+    /// The name of the type of the value, such as `MapSubChunk`.
+    pub type_name: &'static str,
+    /// The position of the value in the list it belongs to, if any.
+    pub index: Option<usize>,
+    /// The offset of the first byte of the value from the start of the entry.
+    pub offset: usize,
+}
 
-    fn read_meta_bytes<'a>(
-        &'a self,
-        path: &'a std::path::Path,
-    ) -> bevy::tasks::BoxedFuture<'a, Result<Vec<u8>, bevy::asset::io::AssetReaderError>> {
-        Box::pin(async { Err(bevy::asset::io::AssetReaderError::NotFound(path.to_owned())) })
+impl fmt::Display for DecodeStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.index {
+            Some(index) => write!(f, "{}[{index}]", self.type_name),
+            None => f.write_str(self.type_name),
+        }
     }
 }
 
-#[derive(Debug, Error)]
-pub enum AssetError {
-    #[error("i/o error: {0}")]
-    Io(#[from] std::io::Error),
-    #[error("archive error: {0}")]
-    Zip(#[from] zip::result::ZipError),
-    #[error("decoding error: {0}")]
-    Decoding(#[from] byte::Error),
+/// Decodes the contents of an archive entry, returning the value and the number of bytes read.
+///
+/// On failure, the error records the entry, the nested values that were being read and their
+/// offsets, down to the innermost sequence element, sprite animation or light definition.
+///
+/// Sprite libraries, whose layout depends on the revision of the game files, are decoded with
+/// [`FormatVersion::detect`] instead.
+pub fn decode<'a, T>(bytes: &'a [u8], entry: &str) -> Result<(T, usize), DecodeError>
+where
+    T: TryRead<'a, LittleEndian>,
+{
+    decode_with(bytes, entry, LittleEndian)
+}
+
+fn decode_with<'a, T, C>(bytes: &'a [u8], entry: &str, ctx: C) -> Result<(T, usize), DecodeError>
+where
+    T: TryRead<'a, C>,
+{
+    let offset = &mut 0;
+    let (result, trace) = util::trace_reads(|| util::read_traced::<T, _>(bytes, offset, ctx, None));
+    match result {
+        Ok(value) => Ok((value, *offset)),
+        Err(source) => {
+            let base = bytes.as_ptr() as usize;
+            let path = trace
+                .into_iter()
+                .rev()
+                .map(|read| DecodeStep {
+                    type_name: read.type_name,
+                    index: read.index,
+                    offset: read.address.saturating_sub(base),
+                })
+                .collect();
+            Err(DecodeError {
+                entry: entry.to_owned(),
+                path,
+                source,
+            })
+        }
+    }
 }
@@ -0,0 +1,119 @@
+use std::fmt;
+use std::str::FromStr;
+
+use byte::TryRead;
+use thiserror::Error;
+
+use super::DecodeError;
+
+/// A revision of the layout of the sprite definitions.
+///
+/// Only [`MapSpriteDefinition`](super::MapSpriteDefinition) is versioned, the map chunks having
+/// a single layout. Values whose layout differs between revisions are decoded with the revision
+/// as their context, and the revision of an entry is detected with [`FormatVersion::detect`] when
+/// loading the game files. A game update that changes a layout is supported by adding a
+/// revision and a decoding branch for it, without breaking the files of the older revisions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum FormatVersion {
+    /// The layout used by the current releases of the game.
+    V1,
+    /// A hypothetical older layout, whose sprite definitions have no render size and are drawn
+    /// at the size of their texture.
+    ///
+    /// It follows the element layout described in `doc/engine.md` and hasn't been checked
+    /// against files of an older release of the game, its fixture being derived from the `V1`
+    /// one.
+    V0,
+}
+
+impl FormatVersion {
+    /// The supported revisions, from the newest to the oldest.
+    pub const ALL: &[FormatVersion] = &[FormatVersion::V1, FormatVersion::V0];
+
+    /// The newest revision, which the writers produce.
+    pub const LATEST: FormatVersion = FormatVersion::V1;
+
+    /// Decodes an entry with this revision, returning the value and the number of bytes read.
+    pub fn decode<'a, T>(self, bytes: &'a [u8], entry: &str) -> Result<(T, usize), DecodeError>
+    where
+        T: TryRead<'a, Self>,
+    {
+        super::decode_with::<T, _>(bytes, entry, self)
+    }
+
+    /// Decodes an entry with the given revision, or with the detected one when it's `None`,
+    /// returning the value, the number of bytes read and the revision.
+    pub fn decode_entry<'a, T>(
+        bytes: &'a [u8],
+        entry: &str,
+        version: Option<Self>,
+    ) -> Result<(T, usize, Self), DecodeError>
+    where
+        T: TryRead<'a, Self>,
+    {
+        match version {
+            Some(version) => {
+                let (value, read) = version.decode(bytes, entry)?;
+                Ok((value, read, version))
+            }
+            None => Self::detect(bytes, entry),
+        }
+    }
+
+    /// Decodes an entry with the revision whose layout fits it, returning the value, the number
+    /// of bytes read and the revision.
+    ///
+    /// The revisions are tried from the newest to the oldest. The first one that decodes the
+    /// whole entry is picked, falling back to the first one that decodes the entry with bytes
+    /// left over. When none of them decodes the entry, the error of the newest one is returned.
+    pub fn detect<'a, T>(bytes: &'a [u8], entry: &str) -> Result<(T, usize, Self), DecodeError>
+    where
+        T: TryRead<'a, Self>,
+    {
+        let mut partial = None;
+        let mut error = None;
+        for &version in Self::ALL {
+            match version.decode(bytes, entry) {
+                Ok((value, read)) if read == bytes.len() => return Ok((value, read, version)),
+                Ok((value, read)) => {
+                    partial.get_or_insert((value, read, version));
+                }
+                Err(err) => {
+                    error.get_or_insert(err);
+                }
+            }
+        }
+        match (partial, error) {
+            (Some(decoded), _) => Ok(decoded),
+            (None, Some(err)) => Err(err),
+            (None, None) => unreachable!("there's at least one revision"),
+        }
+    }
+}
+
+impl fmt::Display for FormatVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::V1 => f.write_str("v1"),
+            Self::V0 => f.write_str("v0"),
+        }
+    }
+}
+
+impl FromStr for FormatVersion {
+    type Err = UnknownFormatVersion;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|version| version.to_string() == s)
+            .ok_or_else(|| UnknownFormatVersion(s.to_owned()))
+    }
+}
+
+/// The error returned when parsing the name of a revision that doesn't exist.
+#[derive(Debug, Error)]
+#[error("unknown format version '{0}', expected one of v1, v0")]
+pub struct UnknownFormatVersion(String);
@@ -5,26 +5,203 @@ use std::sync::Arc;
 use byte::ctx::LittleEndian;
 use byte::{BytesExt, TryRead};
 
-use crate::assets::AssetError;
+use crate::assets::{AssetError, parse_chunk_name};
+use crate::util;
 
+/// The lighting of a cell layer.
+#[derive(Debug, Clone)]
 pub struct LightDef {
+    /// The ambient light multiplied with the element colors, in the `[0, 2]` range.
     pub ambiance_light: [f32; 3],
+    /// The shadow color, in the `[0, 1]` range with `0.5` being neutral.
     pub shadows: [f32; 3],
+    /// The light added by nearby light sources, in the `[-0.5, 0.5]` range.
     pub lights: [f32; 3],
+    /// Whether the cell is affected by the outdoor (day/night) lighting.
     pub allow_outdoor_lighting: bool,
+    /// Whether the shadow color differs from the neutral one.
     pub has_shadows: bool,
+    /// The light added at night, scaled by the darkness of the outdoor light.
+    ///
+    /// The light files don't store it, so it's zero for the decoded definitions.
     pub night_light: [f32; 3],
 }
 
 impl LightDef {
+    /// The lighting of cell layers without lighting data, leaving the colors untouched.
+    ///
+    /// Light sources are added to the colors, so none are neutral (see `doc/engine.md`).
     pub const DEFAULT: LightDef = LightDef {
         allow_outdoor_lighting: false,
         ambiance_light: [1f32, 1f32, 1f32],
         shadows: [1f32, 1f32, 1f32],
-        lights: [1f32, 1f32, 1f32],
+        lights: [0f32, 0f32, 0f32],
         has_shadows: false,
         night_light: [0f32, 0f32, 0f32],
     };
+
+    /// Applies the lighting to a color in the `[0, 2]` range used by element colors.
+    ///
+    /// The color is multiplied by the ambient light and the shadows, after which the light
+    /// sources are added. Cells that allow outdoor lighting additionally have their ambient
+    /// light multiplied by the outdoor color and get the night light added as it gets dark.
+    /// The alpha channel is left untouched.
+    pub fn apply(&self, [r, g, b, a]: [f32; 4], outdoor: &OutdoorLight) -> [f32; 4] {
+        let color = [r, g, b];
+        let [r, g, b] = std::array::from_fn(|i| {
+            let mut ambiance = self.ambiance_light[i];
+            let mut light = self.lights[i];
+            if self.allow_outdoor_lighting {
+                ambiance *= outdoor.color[i];
+                light += self.night_light[i] * outdoor.night;
+            }
+            let shadow = if self.has_shadows {
+                self.shadows[i] * 2f32
+            } else {
+                1f32
+            };
+            (color[i] * ambiance * shadow + light).clamp(0f32, 2f32)
+        });
+        [r, g, b, a]
+    }
+}
+
+/// The outdoor lighting conditions, applied to cells that allow outdoor lighting.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutdoorLight {
+    /// The color of the outdoor light, multiplied with the ambient light.
+    pub color: [f32; 3],
+    /// The darkness of the outdoor light, from 0 during the day to 1 at night.
+    pub night: f32,
+}
+
+impl OutdoorLight {
+    pub const DAY: OutdoorLight = OutdoorLight {
+        color: [1f32, 1f32, 1f32],
+        night: 0f32,
+    };
+}
+
+impl Default for OutdoorLight {
+    fn default() -> Self {
+        Self::DAY
+    }
+}
+
+/// A key of a [`DaylightGradient`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DaylightKey {
+    /// The hour of the day at which the key applies, in the `[0, 24)` range.
+    pub hour: f32,
+    /// The outdoor light color at that hour.
+    pub color: [f32; 3],
+    /// The darkness at that hour, from 0 during the day to 1 at night.
+    pub night: f32,
+}
+
+/// A gradient of outdoor light over a 24 hour day, wrapping around at midnight.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DaylightGradient {
+    keys: Vec<DaylightKey>,
+}
+
+impl DaylightGradient {
+    /// Creates a gradient from its keys, in any order.
+    pub fn new(mut keys: Vec<DaylightKey>) -> Self {
+        for key in &mut keys {
+            key.hour = key.hour.rem_euclid(24f32);
+        }
+        keys.sort_by(|a, b| a.hour.total_cmp(&b.hour));
+        Self { keys }
+    }
+
+    /// Returns the keys of the gradient, sorted by hour.
+    pub fn keys(&self) -> &[DaylightKey] {
+        &self.keys
+    }
+
+    /// Returns the keys of the gradient, sorted by hour, allowing their colors to be edited.
+    pub fn keys_mut(&mut self) -> &mut [DaylightKey] {
+        &mut self.keys
+    }
+
+    /// Returns the outdoor light at the given hour of the day.
+    pub fn sample(&self, hour: f32) -> OutdoorLight {
+        let (Some(first), Some(last)) = (self.keys.first(), self.keys.last()) else {
+            return OutdoorLight::DAY;
+        };
+        let hour = hour.rem_euclid(24f32);
+        let next = self.keys.partition_point(|key| key.hour <= hour);
+
+        // the keys before the first one and after the last one wrap around midnight
+        let (from, to, from_hour, to_hour) = match next {
+            0 => (last, first, last.hour - 24f32, first.hour),
+            n if n == self.keys.len() => (last, first, last.hour, first.hour + 24f32),
+            n => {
+                let (from, to) = (&self.keys[n - 1], &self.keys[n]);
+                (from, to, from.hour, to.hour)
+            }
+        };
+        let t = if to_hour > from_hour {
+            (hour - from_hour) / (to_hour - from_hour)
+        } else {
+            0f32
+        };
+
+        OutdoorLight {
+            color: std::array::from_fn(|i| from.color[i] + (to.color[i] - from.color[i]) * t),
+            night: from.night + (to.night - from.night) * t,
+        }
+    }
+}
+
+impl Default for DaylightGradient {
+    fn default() -> Self {
+        let key = |hour, color, night| DaylightKey { hour, color, night };
+        Self::new(vec![
+            key(0f32, [0.35, 0.4, 0.65], 1f32),
+            key(5f32, [0.45, 0.45, 0.7], 0.8),
+            key(7f32, [1f32, 0.8, 0.65], 0.2),
+            key(12f32, [1f32, 1f32, 1f32], 0f32),
+            key(18f32, [1f32, 0.85, 0.7], 0.1),
+            key(20f32, [0.6, 0.5, 0.7], 0.6),
+            key(22f32, [0.35, 0.4, 0.65], 1f32),
+        ])
+    }
+}
+
+/// Reads a light definition as stored in the palette of a [`LightCell`].
+impl<'a> TryRead<'a, LittleEndian> for LightDef {
+    fn try_read(bytes: &'a [u8], ctx: LittleEndian) -> byte::Result<(Self, usize)> {
+        let offset = &mut 0;
+        let outdoor = bytes.read::<u8>(offset, ctx)? != 0;
+        let ambiance = bytes.read::<i32>(offset, ctx)?;
+        let shadow = bytes.read::<i32>(offset, ctx)?;
+        let light = bytes.read::<i32>(offset, ctx)?;
+        let night = [0f32, 0f32, 0f32];
+
+        let def = LightDef {
+            allow_outdoor_lighting: outdoor,
+            ambiance_light: [
+                (ambiance & 0xFF) as f32 / 255f32 * 2f32,
+                (ambiance >> 8 & 0xFF) as f32 / 255f32 * 2f32,
+                (ambiance >> 16 & 0xFF) as f32 / 255f32 * 2f32,
+            ],
+            shadows: [
+                (shadow & 0xFF) as f32 / 255f32,
+                (shadow >> 8 & 0xFF) as f32 / 255f32,
+                (shadow >> 16 & 0xFF) as f32 / 255f32,
+            ],
+            lights: [
+                (light & 0xFF) as f32 / 255f32 - 0.5f32,
+                (light >> 8 & 0xFF) as f32 / 255f32 - 0.5f32,
+                (light >> 16 & 0xFF) as f32 / 255f32 - 0.5f32,
+            ],
+            has_shadows: shadow as u32 != 0xFF80_8080,
+            night_light: night,
+        };
+        Ok((def, *offset))
+    }
 }
 
 #[derive(Clone)]
@@ -43,33 +220,8 @@ impl<'a> TryRead<'a, LittleEndian> for LightCell {
         let defcount = bytes.read::<u16>(offset, ctx)?;
         let mut layer: Vec<Arc<LightDef>> = Vec::with_capacity(18 * 18);
 
-        for _ in 0..defcount {
-            let outdoor = bytes.read::<u8>(offset, ctx)? != 0;
-            let ambiance = bytes.read::<i32>(offset, ctx)?;
-            let shadow = bytes.read::<i32>(offset, ctx)?;
-            let light = bytes.read::<i32>(offset, ctx)?;
-            let night = [0f32, 0f32, 0f32];
-
-            let def = LightDef {
-                allow_outdoor_lighting: outdoor,
-                ambiance_light: [
-                    (ambiance & 0xFF) as f32 / 255f32 * 2f32,
-                    (ambiance >> 8 & 0xFF) as f32 / 255f32 * 2f32,
-                    (ambiance >> 16 & 0xFF) as f32 / 255f32 * 2f32,
-                ],
-                shadows: [
-                    (shadow & 0xFF) as f32 / 255f32,
-                    (shadow >> 8 & 0xFF) as f32 / 255f32,
-                    (shadow >> 16 & 0xFF) as f32 / 255f32,
-                ],
-                lights: [
-                    (light & 0xFF) as f32 / 255f32 - 0.5f32,
-                    (light >> 8 & 0xFF) as f32 / 255f32 - 0.5f32,
-                    (light >> 16 & 0xFF) as f32 / 255f32 - 0.5f32,
-                ],
-                has_shadows: shadow as u32 != 0xFF80_8080,
-                night_light: night,
-            };
+        for index in 0..defcount as usize {
+            let def: LightDef = util::read_traced(bytes, offset, ctx, Some(index))?;
             layer.push(Arc::new(def));
         }
 
@@ -112,15 +264,11 @@ impl LightMap {
 
         for i in 0..archive.len() {
             let mut entry = archive.by_index(i)?;
-            if entry
-                .name()
-                .trim_matches(|c| char::is_numeric(c) || c == '-')
-                == "_"
-            {
+            if parse_chunk_name(entry.name()).is_some() {
                 let mut buffer = Vec::with_capacity(entry.size() as usize);
                 entry.read_to_end(&mut buffer)?;
 
-                let (cell, _) = LightCell::try_read(&buffer, LittleEndian)?;
+                let (cell, _) = super::decode::<LightCell>(&buffer, entry.name())?;
                 let mapx = cell.cell_x / 18;
                 let mapy = cell.cell_y / 18;
                 let hash = mapx << 16 | (mapy & 0xFFFF);
@@ -147,3 +295,140 @@ impl LightMap {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: OutdoorLight, b: OutdoorLight) {
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-5;
+        assert!(
+            a.color.iter().zip(b.color).all(|(&a, b)| close(a, b)) && close(a.night, b.night),
+            "{a:?} != {b:?}"
+        );
+    }
+
+    #[test]
+    fn decoded_definitions_have_no_night_light() {
+        let mut bytes = vec![1];
+        bytes.extend(0x0080_FF40i32.to_le_bytes());
+        bytes.extend(0xFF80_8080u32.to_le_bytes());
+        bytes.extend(0x00FF_8000i32.to_le_bytes());
+        let (def, read) = crate::assets::decode::<LightDef>(&bytes, "0_0").unwrap();
+        assert_eq!(read, 13);
+
+        assert!(def.allow_outdoor_lighting);
+        assert!(!def.has_shadows);
+        assert_eq!(def.ambiance_light[1], 2f32);
+        assert_eq!(def.lights[0], -0.5);
+        assert_eq!(def.lights[2], 0.5);
+        assert_eq!(def.night_light, [0f32; 3]);
+
+        // at full night, a cell only gets the light of its light sources
+        let night = OutdoorLight {
+            color: [1f32; 3],
+            night: 1f32,
+        };
+        let [r, g, b, _] = def.apply([0f32, 0f32, 0f32, 1f32], &night);
+        assert_eq!([r, g, b], [0f32, def.lights[1], 0.5]);
+    }
+
+    #[test]
+    fn default_leaves_colors_untouched() {
+        let color = [0.25, 0.5, 1.5, 0.75];
+        assert_eq!(LightDef::DEFAULT.apply(color, &OutdoorLight::DAY), color);
+        let night = OutdoorLight {
+            color: [0.5; 3],
+            night: 1f32,
+        };
+        assert_eq!(LightDef::DEFAULT.apply(color, &night), color);
+    }
+
+    #[test]
+    fn apply_multiplies_then_adds_the_lights() {
+        let def = LightDef {
+            allow_outdoor_lighting: true,
+            ambiance_light: [2f32, 1f32, 0.5],
+            shadows: [0.25, 0.5, 1f32],
+            lights: [0.25, -0.25, 0f32],
+            has_shadows: true,
+            night_light: [0.5, 0f32, 0.25],
+        };
+        let color = [0.5, 1f32, 1f32, 0.5];
+        assert_eq!(
+            def.apply(color, &OutdoorLight::DAY),
+            [0.75, 0.75, 1f32, 0.5]
+        );
+
+        let dusk = OutdoorLight {
+            color: [0.5, 0.5, 1f32],
+            night: 0.5,
+        };
+        assert_eq!(def.apply(color, &dusk), [0.75, 0.25, 1.125, 0.5]);
+
+        // the shadows and the outdoor light are ignored when the cell doesn't use them
+        let def = LightDef {
+            allow_outdoor_lighting: false,
+            has_shadows: false,
+            ..def
+        };
+        assert_eq!(def.apply(color, &dusk), [1.25, 0.75, 0.5, 0.5]);
+    }
+
+    #[test]
+    fn apply_clamps_the_color() {
+        let def = LightDef {
+            ambiance_light: [2f32; 3],
+            lights: [0.5, -0.5, 0f32],
+            ..LightDef::DEFAULT
+        };
+        assert_eq!(
+            def.apply([1.5, 0.2, 1f32, 1f32], &OutdoorLight::DAY),
+            [2f32, 0f32, 2f32, 1f32]
+        );
+    }
+
+    #[test]
+    fn sample_wraps_around_midnight() {
+        let gradient = DaylightGradient::default();
+        let night = |hour| gradient.sample(hour).night;
+
+        // the default gradient is fully dark from 22h through midnight
+        assert_close(gradient.sample(23f32), gradient.sample(0f32));
+        assert_eq!(night(23f32), 1f32);
+        assert_close(gradient.sample(-1f32), gradient.sample(23f32));
+        assert_close(gradient.sample(24f32), gradient.sample(0f32));
+        assert_close(gradient.sample(36f32), gradient.sample(12f32));
+    }
+
+    #[test]
+    fn sample_interpolates_across_midnight() {
+        let light = |night| OutdoorLight {
+            color: [1f32 - night; 3],
+            night,
+        };
+        let key = |hour, night| DaylightKey {
+            hour,
+            color: light(night).color,
+            night,
+        };
+        // keys out of order and outside of the day, with none at midnight
+        let gradient = DaylightGradient::new(vec![key(20f32, 1f32), key(28f32, 0f32)]);
+        assert_eq!(gradient.keys()[0].hour, 4f32);
+
+        // 20h to 4h spans 8 hours over midnight
+        assert_close(gradient.sample(22f32), light(0.75));
+        assert_close(gradient.sample(0f32), light(0.5));
+        assert_close(gradient.sample(2f32), light(0.25));
+        // 4h to 20h is an ordinary interpolation
+        assert_close(gradient.sample(12f32), light(0.5));
+        assert_close(gradient.sample(4f32), light(0f32));
+        assert_close(gradient.sample(20f32), light(1f32));
+    }
+
+    #[test]
+    fn sample_without_keys_is_day() {
+        let gradient = DaylightGradient::new(Vec::new());
+        assert_eq!(gradient.sample(3f32), OutdoorLight::DAY);
+    }
+}
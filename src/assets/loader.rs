@@ -0,0 +1,345 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+#[cfg(not(any(unix, windows)))]
+use std::sync::Mutex;
+
+use bevy::asset::io::{AssetReaderError, ErasedAssetReader, VecReader};
+use bevy::asset::{AssetLoader, RenderAssetUsages};
+use bevy::image::Image;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use hashbrown::{HashMap, HashSet};
+
+use super::{AssetError, Tgam};
+
+#[derive(Debug, Default)]
+pub struct TgamLoader;
+
+impl AssetLoader for TgamLoader {
+    type Asset = Image;
+    type Error = AssetError;
+    type Settings = ();
+
+    async fn load(
+        &self,
+        reader: &mut dyn bevy::asset::io::Reader,
+        _settings: &Self::Settings,
+        load_context: &mut bevy::asset::LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+
+        let entry = load_context.path().to_string_lossy();
+        let (tgam, _) = super::decode::<Tgam<'_>>(&bytes, &entry)?;
+        let extent = Extent3d {
+            width: tgam.width(),
+            height: tgam.height(),
+            depth_or_array_layers: 1,
+        };
+        let img = Image::new(
+            extent,
+            TextureDimension::D2,
+            tgam.rgba().to_vec(),
+            TextureFormat::Rgba8Unorm,
+            RenderAssetUsages::RENDER_WORLD,
+        );
+        Ok(img)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tgam"]
+    }
+}
+
+/// An asset source reading from a zip archive.
+///
+/// The central directory of the archive is parsed once and shared between clones, every read
+/// works on its own cheap copy of the archive, so entries can be decompressed in parallel.
+#[derive(Debug, Clone)]
+pub struct JarAssetSource {
+    archive: zip::ZipArchive<SharedFile>,
+    /// The paths of the entries in each directory, including nested directories.
+    directories: Arc<HashMap<PathBuf, Vec<PathBuf>>>,
+}
+
+impl JarAssetSource {
+    pub fn new(path: impl AsRef<Path>) -> Result<Self, AssetError> {
+        let archive = zip::ZipArchive::new(SharedFile::open(path)?)?;
+        let directories = Arc::new(index_directories(archive.file_names()));
+        Ok(Self {
+            archive,
+            directories,
+        })
+    }
+
+    /// Reads the contents of an entry of the archive.
+    ///
+    /// This can be called from multiple threads at once without any locking.
+    pub fn read_entry(&self, name: &str) -> Result<Vec<u8>, zip::result::ZipError> {
+        let mut archive = self.archive.clone();
+        let mut entry = archive.by_name(name)?;
+        let mut bytes = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Returns the names of all entries in the archive.
+    pub fn entry_names(&self) -> impl Iterator<Item = &str> {
+        self.archive.file_names()
+    }
+
+    /// Returns whether the archive has a file entry at the given path.
+    fn is_file(&self, path: &Path) -> bool {
+        let parent = path.parent().unwrap_or(Path::new(""));
+        self.directories
+            .get(parent)
+            .is_some_and(|entries| entries.iter().any(|entry| entry == path))
+            && !self.directories.contains_key(path)
+    }
+}
+
+/// A read-only file handle shared between clones, each with its own cursor.
+///
+/// Reads are positional and don't touch the cursor of the underlying file, so clones can be
+/// used from multiple threads at once. Elsewhere than on unix and windows, the file is locked
+/// while it's seeked and read instead.
+#[derive(Debug, Clone)]
+struct SharedFile {
+    file: Arc<FileHandle>,
+    len: u64,
+    position: u64,
+}
+
+impl SharedFile {
+    fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        Ok(Self {
+            file: Arc::new(FileHandle::from(file)),
+            len,
+            position: 0,
+        })
+    }
+}
+
+impl Read for SharedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = read_at(&self.file, buf, self.position)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for SharedFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = position.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "seek to a negative position")
+        })?;
+        Ok(self.position)
+    }
+}
+
+/// The file read by a [`SharedFile`], locked on the platforms without positional reads.
+#[cfg(any(unix, windows))]
+type FileHandle = File;
+#[cfg(not(any(unix, windows)))]
+type FileHandle = Mutex<File>;
+
+#[cfg(unix)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_read(file, buf, offset)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn read_at(file: &Mutex<File>, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    let mut file = file.lock().unwrap();
+    file.seek(SeekFrom::Start(offset))?;
+    file.read(buf)
+}
+
+/// Groups the entry names of an archive by their parent directory, adding the directories
+/// that are only implied by the paths of their entries.
+fn index_directories<'a>(names: impl Iterator<Item = &'a str>) -> HashMap<PathBuf, Vec<PathBuf>> {
+    let mut directories: HashMap<PathBuf, HashSet<PathBuf>> = HashMap::new();
+    directories.insert(PathBuf::new(), HashSet::new());
+
+    for name in names {
+        let mut path = Path::new(name.trim_end_matches('/'));
+        if name.ends_with('/') {
+            directories.entry(path.to_owned()).or_default();
+        }
+        while let Some(parent) = path.parent() {
+            directories
+                .entry(parent.to_owned())
+                .or_default()
+                .insert(path.to_owned());
+            path = parent;
+        }
+    }
+
+    directories
+        .into_iter()
+        .map(|(dir, entries)| {
+            let mut entries: Vec<_> = entries.into_iter().collect();
+            entries.sort();
+            (dir, entries)
+        })
+        .collect()
+}
+
+impl ErasedAssetReader for JarAssetSource {
+    fn read<'a>(
+        &'a self,
+        path: &'a std::path::Path,
+    ) -> bevy::tasks::BoxedFuture<
+        'a,
+        Result<Box<dyn bevy::asset::io::Reader + 'a>, bevy::asset::io::AssetReaderError>,
+    > {
+        Box::pin(async {
+            let bytes = self
+                .read_entry(&path.to_string_lossy())
+                .map_err(|err| match err {
+                    zip::result::ZipError::Io(error) => error,
+                    zip::result::ZipError::FileNotFound => std::io::Error::new(
+                        std::io::ErrorKind::NotFound,
+                        format!("'{}' not found ({err})", path.display()),
+                    ),
+                    _ => std::io::Error::other(err),
+                })?;
+            Ok(Box::new(VecReader::new(bytes)) as Box<dyn bevy::asset::io::Reader>)
+        })
+    }
+
+    fn read_meta<'a>(
+        &'a self,
+        path: &'a std::path::Path,
+    ) -> bevy::tasks::BoxedFuture<
+        'a,
+        Result<Box<dyn bevy::asset::io::Reader + 'a>, bevy::asset::io::AssetReaderError>,
+    > {
+        // the archives don't contain any meta files
+        Box::pin(async { Err(AssetReaderError::NotFound(path.to_owned())) })
+    }
+
+    fn read_directory<'a>(
+        &'a self,
+        path: &'a std::path::Path,
+    ) -> bevy::tasks::BoxedFuture<
+        'a,
+        Result<Box<bevy::asset::io::PathStream>, bevy::asset::io::AssetReaderError>,
+    > {
+        Box::pin(async {
+            let entries = self
+                .directories
+                .get(path)
+                .ok_or_else(|| AssetReaderError::NotFound(path.to_owned()))?;
+            let stream = bevy::tasks::futures_lite::stream::iter(entries.clone());
+            Ok(Box::new(stream) as Box<bevy::asset::io::PathStream>)
+        })
+    }
+
+    fn is_directory<'a>(
+        &'a self,
+        path: &'a std::path::Path,
+    ) -> bevy::tasks::BoxedFuture<'a, Result<bool, bevy::asset::io::AssetReaderError>> {
+        Box::pin(async {
+            if self.directories.contains_key(path) {
+                Ok(true)
+            } else if self.is_file(path) {
+                Ok(false)
+            } else {
+                Err(AssetReaderError::NotFound(path.to_owned()))
+            }
+        })
+    }
+
+    fn read_meta_bytes<'a>(
+        &'a self,
+        path: &'a std::path::Path,
+    ) -> bevy::tasks::BoxedFuture<'a, Result<Vec<u8>, bevy::asset::io::AssetReaderError>> {
+        Box::pin(async { Err(AssetReaderError::NotFound(path.to_owned())) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::{Cursor, Write};
+
+    use bevy::tasks::block_on;
+    use zip::write::SimpleFileOptions;
+
+    use super::*;
+
+    fn paths(paths: &[&str]) -> Vec<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    /// Writes an archive with the given entries to the temporary directory and opens it.
+    fn source(name: &str, entries: &[&str]) -> (JarAssetSource, PathBuf) {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for entry in entries {
+            match entry.strip_suffix('/') {
+                Some(dir) => writer.add_directory(dir, SimpleFileOptions::default()),
+                None => writer.start_file(*entry, SimpleFileOptions::default()),
+            }
+            .unwrap();
+        }
+        let path = std::env::temp_dir().join(format!("vakfu-{}-{name}.jar", std::process::id()));
+        fs::write(&path, writer.finish().unwrap().into_inner()).unwrap();
+        (JarAssetSource::new(&path).unwrap(), path)
+    }
+
+    #[test]
+    fn nested_paths_imply_their_directories() {
+        let directories =
+            index_directories(["gfx/1.tgam", "gfx/sub/2.tgam", "data.lib"].into_iter());
+        assert_eq!(directories[Path::new("")], paths(&["data.lib", "gfx"]));
+        assert_eq!(
+            directories[Path::new("gfx")],
+            paths(&["gfx/1.tgam", "gfx/sub"])
+        );
+        assert_eq!(
+            directories[Path::new("gfx/sub")],
+            paths(&["gfx/sub/2.tgam"])
+        );
+        assert_eq!(directories.len(), 3);
+    }
+
+    #[test]
+    fn explicit_directory_entries_are_indexed_even_when_empty() {
+        let directories = index_directories(["gfx/", "gfx/1.tgam", "empty/"].into_iter());
+        assert_eq!(directories[Path::new("")], paths(&["empty", "gfx"]));
+        assert_eq!(directories[Path::new("gfx")], paths(&["gfx/1.tgam"]));
+        assert!(directories[Path::new("empty")].is_empty());
+    }
+
+    #[test]
+    fn files_and_directories_are_told_apart() {
+        let (source, path) = source("kinds", &["gfx/", "gfx/1.tgam", "empty/"]);
+        assert!(source.is_file(Path::new("gfx/1.tgam")));
+        assert!(!source.is_file(Path::new("gfx")));
+        assert!(!source.is_file(Path::new("gfx/2.tgam")));
+        assert!(!source.is_file(Path::new("missing/1.tgam")));
+
+        assert!(block_on(source.is_directory(Path::new("gfx"))).unwrap());
+        assert!(block_on(source.is_directory(Path::new("empty"))).unwrap());
+        assert!(!block_on(source.is_directory(Path::new("gfx/1.tgam"))).unwrap());
+        assert!(matches!(
+            block_on(source.is_directory(Path::new("gfx/2.tgam"))),
+            Err(AssetReaderError::NotFound(_))
+        ));
+        fs::remove_file(path).unwrap();
+    }
+}
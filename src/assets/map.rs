@@ -1,17 +1,26 @@
-use std::io::{Read, Seek};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, Write};
+use std::path::Path;
 
-use byte::TryRead;
-use byte::ctx::LittleEndian;
+use byte::ctx::{Endianess, LittleEndian};
+use byte::{BytesExt, TryRead, TryWrite};
+use hashbrown::HashMap;
+use zip::CompressionMethod;
+use zip::write::SimpleFileOptions;
 
 use super::AssetError;
-use crate::util::{WithSize, WithSizePrefix};
+use crate::util::{self, WithSize, WithSizePrefix};
 
 const CELL_WIDTH: i32 = 86;
 const CELL_HEIGHT: i32 = 43;
 const ELEVATION_UNIT: i32 = 10;
 
+/// The number of cells along each side of a map chunk.
+pub const CHUNK_SIZE: i32 = 18;
+
 /// A map, consisting of multiple chunks.
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Map {
     /// The chunks that make up the map.
     chunks: Vec<MapChunk>,
@@ -20,24 +29,34 @@ pub struct Map {
 impl Map {
     /// Loads a map from a zip archive containing chunk files.
     pub fn load<R: Read + Seek>(input: R) -> Result<Map, AssetError> {
-        let mut archive = zip::ZipArchive::new(input)?;
-        let mut chunks = Vec::with_capacity(archive.len());
+        let mut archive = MapArchive::new(input)?;
+        let mut indices: Vec<usize> = archive.chunks.values().copied().collect();
+        indices.sort_unstable();
+
+        let chunks = indices
+            .into_iter()
+            .map(|index| {
+                let (name, bytes) = archive.read_entry(index)?;
+                MapChunk::decode(&bytes, &name)
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Map { chunks })
+    }
 
-        for i in 0..archive.len() {
-            let mut file = archive.by_index(i)?;
-            if file
-                .name()
-                .trim_matches(|c| char::is_numeric(c) || c == '-')
-                == "_"
-            {
-                let mut buffer = Vec::with_capacity(file.size() as usize);
-                file.read_to_end(&mut buffer)?;
-
-                let (chunk, _) = MapChunk::try_read(&buffer, LittleEndian)?;
-                chunks.push(chunk);
-            }
+    /// Saves the map as a zip archive with a file for each chunk, named after its chunk
+    /// coordinates.
+    pub fn save<W: Write + Seek>(&self, output: W) -> Result<(), AssetError> {
+        let mut writer = zip::ZipWriter::new(output);
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+        for chunk in &self.chunks {
+            let bytes = util::write_to_vec(chunk, LittleEndian).map_err(AssetError::Encoding)?;
+            let (x, y) = chunk.coords();
+            writer.start_file(format!("{x}_{y}"), options)?;
+            writer.write_all(&bytes)?;
         }
-        Ok(Map { chunks })
+        writer.finish()?;
+        Ok(())
     }
 
     /// Returns the chunks of the map.
@@ -46,8 +65,79 @@ impl Map {
     }
 }
 
+/// A map archive whose chunks are parsed on demand.
+///
+/// The chunk files are indexed by the `x_y` chunk coordinates in their names, the chunk at
+/// `(x, y)` covering the cells starting at `(x * CHUNK_SIZE, y * CHUNK_SIZE)`.
+pub struct MapArchive<R> {
+    archive: zip::ZipArchive<R>,
+    chunks: HashMap<(i32, i32), usize>,
+}
+
+impl MapArchive<BufReader<File>> {
+    /// Opens a map archive on disk.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, AssetError> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read + Seek> MapArchive<R> {
+    /// Indexes the chunk files of a zip archive without reading them.
+    pub fn new(input: R) -> Result<Self, AssetError> {
+        let mut archive = zip::ZipArchive::new(input)?;
+        let mut chunks = HashMap::new();
+        for i in 0..archive.len() {
+            if let Some(coords) = parse_chunk_name(archive.by_index_raw(i)?.name()) {
+                chunks.insert(coords, i);
+            }
+        }
+        Ok(Self { archive, chunks })
+    }
+
+    /// Returns the coordinates of all chunks in the archive.
+    pub fn chunk_coords(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        self.chunks.keys().copied()
+    }
+
+    /// Reads and parses the chunk at the given chunk coordinates, or returns `None` if
+    /// there's no such chunk.
+    pub fn load_chunk(&mut self, x: i32, y: i32) -> Result<Option<MapChunk>, AssetError> {
+        match self.read_chunk_entry(x, y)? {
+            Some((name, bytes)) => MapChunk::decode(&bytes, &name).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads the file of the chunk at the given chunk coordinates without parsing it,
+    /// returning its name and content, or `None` if there's no such chunk.
+    pub fn read_chunk_entry(
+        &mut self,
+        x: i32,
+        y: i32,
+    ) -> Result<Option<(String, Vec<u8>)>, AssetError> {
+        match self.chunks.get(&(x, y)) {
+            Some(&index) => self.read_entry(index).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn read_entry(&mut self, index: usize) -> Result<(String, Vec<u8>), AssetError> {
+        let mut file = self.archive.by_index(index)?;
+        let mut buffer = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut buffer)?;
+        Ok((file.name().to_owned(), buffer))
+    }
+}
+
+/// Parses the `x_y` coordinates from the name of a chunk file, returning `None` for the other
+/// entries of a map archive.
+pub fn parse_chunk_name(name: &str) -> Option<(i32, i32)> {
+    let (x, y) = name.split_once('_')?;
+    Some((x.parse().ok()?, y.parse().ok()?))
+}
+
 /// A single chunk of a map.
-#[derive(Debug, TryRead)]
+#[derive(Debug, PartialEq, Eq, TryRead)]
 pub struct MapChunk {
     /// The minimum X cell coordinate of the chunk's bounds.
     min_x: i32,
@@ -79,6 +169,41 @@ pub struct MapChunk {
 }
 
 impl MapChunk {
+    /// Parses the content of a chunk file read with [`MapArchive::read_chunk_entry`], `entry`
+    /// naming it in the errors.
+    pub fn decode(bytes: &[u8], entry: &str) -> Result<MapChunk, AssetError> {
+        let (chunk, _) = super::decode::<MapChunk>(bytes, entry)?;
+        Ok(chunk)
+    }
+
+    /// Returns the coordinates of the chunk, in units of [`CHUNK_SIZE`] cells.
+    pub fn coords(&self) -> (i32, i32) {
+        (
+            self.map_x.div_euclid(CHUNK_SIZE),
+            self.map_y.div_euclid(CHUNK_SIZE),
+        )
+    }
+
+    /// Returns the minimum `(x, y, z)` cell coordinates of the chunk's bounds.
+    pub fn min(&self) -> (i32, i32, i16) {
+        (self.min_x, self.min_y, self.min_z)
+    }
+
+    /// Returns the maximum `(x, y, z)` cell coordinates of the chunk's bounds.
+    pub fn max(&self) -> (i32, i32, i16) {
+        (self.max_x, self.max_y, self.max_z)
+    }
+
+    /// Returns the groups used by the elements in this chunk.
+    pub fn groups(&self) -> &[Group] {
+        &self.groups
+    }
+
+    /// Returns the colors used by the elements in this chunk.
+    pub fn colors(&self) -> &[Color] {
+        &self.colors
+    }
+
     /// Returns an iterator over the elements in the chunk.
     pub fn elements(&self) -> impl Iterator<Item = MapElementDetails<'_>> {
         self.sub_chunks.iter().flat_map(move |sub| {
@@ -97,8 +222,55 @@ impl MapChunk {
     }
 }
 
+impl<C: Endianess> TryWrite<C> for &MapChunk {
+    fn try_write(self, bytes: &mut [u8], ctx: C) -> byte::Result<usize> {
+        let offset = &mut 0;
+        bytes.write(offset, self.min_x, ctx)?;
+        bytes.write(offset, self.min_y, ctx)?;
+        bytes.write(offset, self.min_z, ctx)?;
+        bytes.write(offset, self.max_x, ctx)?;
+        bytes.write(offset, self.max_y, ctx)?;
+        bytes.write(offset, self.max_z, ctx)?;
+        bytes.write(
+            offset,
+            self.groups.as_slice(),
+            WithSizePrefix::<_, u16>::new(ctx),
+        )?;
+        bytes.write(
+            offset,
+            self.colors.as_slice(),
+            WithSizePrefix::<_, u16>::new(ctx),
+        )?;
+        bytes.write(offset, self.map_x, ctx)?;
+        bytes.write(offset, self.map_y, ctx)?;
+        bytes.write(
+            offset,
+            self.sub_chunks.as_slice(),
+            WithSizePrefix::<_, u16>::new(ctx),
+        )?;
+        Ok(*offset)
+    }
+}
+
+/// Serializes the chunk with its elements resolved, as returned by [`MapChunk::elements`].
+#[cfg(feature = "serde")]
+impl serde::Serialize for MapChunk {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("MapChunk", 6)?;
+        state.serialize_field("coords", &self.coords())?;
+        state.serialize_field("min", &self.min())?;
+        state.serialize_field("max", &self.max())?;
+        state.serialize_field("groups", &self.groups)?;
+        state.serialize_field("colors", &self.colors)?;
+        state.serialize_field("elements", &self.elements().collect::<Vec<_>>())?;
+        state.end()
+    }
+}
+
 /// Represents a sub-section of a chunk, defining a rectangular area of cells.
-#[derive(Debug, TryRead)]
+#[derive(Debug, PartialEq, Eq, TryRead)]
 struct MapSubChunk {
     /// The minimum relative X coordinate of the sub-chunk area.
     min_x: i8,
@@ -114,16 +286,42 @@ struct MapSubChunk {
     cells: Vec<MapCell>,
 }
 
+impl<C: Endianess> TryWrite<C> for &MapSubChunk {
+    fn try_write(self, bytes: &mut [u8], ctx: C) -> byte::Result<usize> {
+        let offset = &mut 0;
+        bytes.write(offset, self.min_x, ctx)?;
+        bytes.write(offset, self.max_x, ctx)?;
+        bytes.write(offset, self.min_y, ctx)?;
+        bytes.write(offset, self.max_y, ctx)?;
+        let count = (self.max_x - self.min_x) as usize * (self.max_y - self.min_y) as usize;
+        bytes.write(offset, self.cells.as_slice(), WithSize::new(ctx, count))?;
+        Ok(*offset)
+    }
+}
+
 /// A single cell within a map sub-chunk, containing rendering elements.
-#[derive(Debug, TryRead)]
+#[derive(Debug, PartialEq, Eq, TryRead)]
 struct MapCell {
     /// The map sprites/elements positioned within this cell.
     #[byte(ctx = WithSizePrefix::<_, u8>::new(ctx))]
     elements: Vec<MapSrite>,
 }
 
+impl<C: Endianess> TryWrite<C> for &MapCell {
+    fn try_write(self, bytes: &mut [u8], ctx: C) -> byte::Result<usize> {
+        let offset = &mut 0;
+        bytes.write(
+            offset,
+            self.elements.as_slice(),
+            WithSizePrefix::<_, u8>::new(ctx),
+        )?;
+        Ok(*offset)
+    }
+}
+
 /// A group of map elements.
-#[derive(Debug, TryRead)]
+#[derive(Debug, PartialEq, Eq, TryRead)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Group {
     /// The unique key associated with the group.
     key: i32,
@@ -143,10 +341,25 @@ impl Group {
     pub fn layer(&self) -> u8 {
         self.layer
     }
+
+    /// Returns the identifier of the group.
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+}
+
+impl<C: Endianess> TryWrite<C> for &Group {
+    fn try_write(self, bytes: &mut [u8], ctx: C) -> byte::Result<usize> {
+        let offset = &mut 0;
+        bytes.write(offset, self.key, ctx)?;
+        bytes.write(offset, self.layer, ctx)?;
+        bytes.write(offset, self.id, ctx)?;
+        Ok(*offset)
+    }
 }
 
 /// Represents the raw properties of a sprite placed on a map cell.
-#[derive(Debug, TryRead)]
+#[derive(Debug, PartialEq, Eq, TryRead)]
 struct MapSrite {
     /// The Z (altitude) coordinate of the element.
     cell_z: i16,
@@ -164,8 +377,23 @@ struct MapSrite {
     color_index: u16,
 }
 
+impl<C: Endianess> TryWrite<C> for &MapSrite {
+    fn try_write(self, bytes: &mut [u8], ctx: C) -> byte::Result<usize> {
+        let offset = &mut 0;
+        bytes.write(offset, self.cell_z, ctx)?;
+        bytes.write(offset, self.height, ctx)?;
+        bytes.write(offset, self.altitude_order, ctx)?;
+        bytes.write(offset, self.tag, ctx)?;
+        bytes.write(offset, self.definition_id, ctx)?;
+        bytes.write(offset, self.group_index, ctx)?;
+        bytes.write(offset, self.color_index, ctx)?;
+        Ok(*offset)
+    }
+}
+
 /// An RGB color definition.
-#[derive(Debug, Default, Clone, Copy, TryRead)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, TryRead)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Rgb {
     /// The red component.
     r: i8,
@@ -176,7 +404,8 @@ pub struct Rgb {
 }
 
 /// An RGBA color definition.
-#[derive(Debug, Default, Clone, Copy, TryRead)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, TryRead)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Rgba {
     /// The red component.
     r: i8,
@@ -188,6 +417,27 @@ pub struct Rgba {
     a: i8,
 }
 
+impl<C: Endianess> TryWrite<C> for &Rgb {
+    fn try_write(self, bytes: &mut [u8], ctx: C) -> byte::Result<usize> {
+        let offset = &mut 0;
+        bytes.write(offset, self.r, ctx)?;
+        bytes.write(offset, self.g, ctx)?;
+        bytes.write(offset, self.b, ctx)?;
+        Ok(*offset)
+    }
+}
+
+impl<C: Endianess> TryWrite<C> for &Rgba {
+    fn try_write(self, bytes: &mut [u8], ctx: C) -> byte::Result<usize> {
+        let offset = &mut 0;
+        bytes.write(offset, self.r, ctx)?;
+        bytes.write(offset, self.g, ctx)?;
+        bytes.write(offset, self.b, ctx)?;
+        bytes.write(offset, self.a, ctx)?;
+        Ok(*offset)
+    }
+}
+
 impl Rgba {
     pub fn new(r: f32, g: f32, b: f32, a: f32) -> Self {
         Self {
@@ -226,7 +476,8 @@ impl From<Rgb> for Rgba {
 /// - `0x1` (bit 0): Indicates the presence of an RGB tint.
 /// - `0x2` (bit 1): Indicates the presence of an Alpha channel.
 /// - `0x4` (bit 2): Indicates the presence of a color gradient (requiring two color values).
-#[derive(Debug, Clone, Copy, TryRead)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryRead)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 #[byte(tag_type = u8)]
 pub enum Color {
     /// Represents no specific color.
@@ -246,6 +497,49 @@ pub enum Color {
     RgbaGradient(Rgba, Rgba),
 }
 
+impl Color {
+    /// Returns the start and end colors of the gradient, both being the same for solid colors.
+    pub fn gradient(self) -> (Rgba, Rgba) {
+        match self {
+            Color::RgbGradient(start, end) => (start.into(), end.into()),
+            Color::RgbaGradient(start, end) => (start, end),
+            color => {
+                let rgba = Rgba::from(color);
+                (rgba, rgba)
+            }
+        }
+    }
+}
+
+impl<C: Endianess> TryWrite<C> for &Color {
+    fn try_write(self, bytes: &mut [u8], ctx: C) -> byte::Result<usize> {
+        let offset = &mut 0;
+        match self {
+            Color::None => bytes.write(offset, 0x0u8, ctx)?,
+            Color::Rgb(rgb) => {
+                bytes.write(offset, 0x1u8, ctx)?;
+                bytes.write(offset, rgb, ctx)?;
+            }
+            Color::Rgba(rgba) => {
+                bytes.write(offset, 0x3u8, ctx)?;
+                bytes.write(offset, rgba, ctx)?;
+            }
+            Color::RgbGradient(start, end) => {
+                bytes.write(offset, 0x5u8, ctx)?;
+                bytes.write(offset, start, ctx)?;
+                bytes.write(offset, end, ctx)?;
+            }
+            Color::RgbaGradient(start, end) => {
+                bytes.write(offset, 0x7u8, ctx)?;
+                bytes.write(offset, start, ctx)?;
+                bytes.write(offset, end, ctx)?;
+            }
+        }
+        Ok(*offset)
+    }
+}
+
+/// Converts the color to a single RGBA value, using the start color of gradients.
 impl From<Color> for Rgba {
     fn from(color: Color) -> Self {
         match color {
@@ -283,27 +577,55 @@ impl<'a> MapElementDetails<'a> {
     }
 
     /// Returns the group this element belongs to.
+    ///
+    /// Panics if the group index is out of bounds, see [`Self::try_group`].
     pub fn group(&self) -> &'a Group {
         &self.chunk.groups[self.element.group_index as usize]
     }
 
+    /// Returns the group this element belongs to, or `None` if its index is out of bounds.
+    pub fn try_group(&self) -> Option<&'a Group> {
+        self.chunk.groups.get(self.element.group_index as usize)
+    }
+
     /// Returns the color of the element.
+    ///
+    /// Panics if the color index is out of bounds, see [`Self::try_color`].
     pub fn color(&self) -> Color {
         self.chunk.colors[self.element.color_index as usize]
     }
 
+    /// Returns the color of the element, or `None` if its index is out of bounds.
+    pub fn try_color(&self) -> Option<Color> {
+        self.chunk
+            .colors
+            .get(self.element.color_index as usize)
+            .copied()
+    }
+
     /// Computes the screen position of the element.
     pub fn screen_position(&self) -> (f32, f32) {
         let height = i32::from(self.element.cell_z) - i32::from(self.element.height);
         iso_to_screen(self.cell_x, self.cell_y, height)
     }
 
+    /// Returns the X cell coordinate of the element.
+    pub fn cell_x(&self) -> i32 {
+        self.cell_x
+    }
+
+    /// Returns the Y cell coordinate of the element.
+    pub fn cell_y(&self) -> i32 {
+        self.cell_y
+    }
+
+    /// Returns the Z (altitude) coordinate of the element.
+    pub fn cell_z(&self) -> i16 {
+        self.element.cell_z
+    }
+
     /// Computes the hashcode used primarily for determining rendering depth order (z-sorting).
     /// It relies on cell coordinates (`x` and `y`) along with the element's `altitude_order`.
-    pub fn cell_x(&self) -> i32 { self.cell_x }
-
-    pub fn cell_y(&self) -> i32 { self.cell_y }
-    
     pub fn hashcode(&self) -> i64 {
         (self.element.altitude_order as i64 & 0x1FFFi64) << 6i64
             | ((self.cell_x as i64 + 8192i64) & 0x3FFFi64) << 19i64
@@ -311,6 +633,30 @@ impl<'a> MapElementDetails<'a> {
     }
 }
 
+/// Serializes the group and the color as `null` when their index is out of bounds.
+#[cfg(feature = "serde")]
+impl serde::Serialize for MapElementDetails<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let (screen_x, screen_y) = self.screen_position();
+        let mut state = serializer.serialize_struct("MapElementDetails", 12)?;
+        state.serialize_field("cell_x", &self.cell_x)?;
+        state.serialize_field("cell_y", &self.cell_y)?;
+        state.serialize_field("cell_z", &self.element.cell_z)?;
+        state.serialize_field("height", &self.element.height)?;
+        state.serialize_field("altitude_order", &self.element.altitude_order)?;
+        state.serialize_field("screen_x", &screen_x)?;
+        state.serialize_field("screen_y", &screen_y)?;
+        state.serialize_field("hashcode", &self.hashcode())?;
+        state.serialize_field("tag", &self.element.tag)?;
+        state.serialize_field("definition_id", &self.element.definition_id)?;
+        state.serialize_field("group", &self.try_group())?;
+        state.serialize_field("color", &self.try_color())?;
+        state.end()
+    }
+}
+
 /// Converts isometric coordinates `(x, y)` and `height` to screen coordinates.
 ///
 /// This transformation uses specific scaling factors:
@@ -321,3 +667,19 @@ pub fn iso_to_screen(x: i32, y: i32, height: i32) -> (f32, f32) {
     let fy = ((-(x + y) * CELL_HEIGHT) as f32 / 2.) + (height * ELEVATION_UNIT) as f32;
     (fx, fy)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunk_names_are_coordinates() {
+        assert_eq!(parse_chunk_name("1_-2"), Some((1, -2)));
+        assert_eq!(parse_chunk_name("-3_0"), Some((-3, 0)));
+        assert_eq!(parse_chunk_name("META-INF/MANIFEST.MF"), None);
+        assert_eq!(parse_chunk_name("1_2_3"), None);
+        assert_eq!(parse_chunk_name("1-2_3"), None);
+        assert_eq!(parse_chunk_name("1_"), None);
+        assert_eq!(parse_chunk_name("_"), None);
+    }
+}
@@ -1,17 +1,22 @@
-use std::io::{Read, Seek};
+use std::io::{Read, Seek, Write};
 use std::ops;
 use std::sync::Arc;
 
-use anyhow::anyhow;
 use bitfield_struct::bitfield;
 use byte::ctx::{Endianess, LittleEndian};
-use byte::{BytesExt, TryRead};
+use byte::{BytesExt, TryRead, TryWrite};
 use hashbrown::HashMap;
+use zip::CompressionMethod;
+use zip::write::SimpleFileOptions;
 
-use super::AssetError;
+use super::{AssetError, FormatVersion};
+use crate::util;
 
 /// Defines the properties of a map sprite.
-#[derive(Debug, TryRead)]
+///
+/// The layout read with an endianness context is the one of [`FormatVersion::V1`].
+#[derive(Debug, PartialEq, Eq, TryRead)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct MapSpriteDefinition {
     /// The unique identifier for this sprite definition.
     id: i32,
@@ -49,6 +54,11 @@ pub struct MapSpriteDefinition {
 }
 
 impl MapSpriteDefinition {
+    /// Returns the unique identifier of the definition.
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
     /// Returns the original coordinates `(x, y)` of the sprite.
     pub fn origin(&self) -> (i16, i16) {
         (self.origin_x, self.origin_y)
@@ -74,10 +84,118 @@ impl MapSpriteDefinition {
         (self.render_width, self.render_height)
     }
 
+    /// Returns the visual height of the sprite.
+    pub fn visual_height(&self) -> u8 {
+        self.visual_height
+    }
+
+    /// Returns the visibility mask of the sprite.
+    pub fn visibility_mask(&self) -> u8 {
+        self.visibility_mask
+    }
+
+    /// Returns the export mask of the sprite.
+    pub fn export_mask(&self) -> u8 {
+        self.export_mask
+    }
+
+    /// Returns the ID of the shader used to draw the sprite.
+    pub fn shader(&self) -> u8 {
+        self.shader
+    }
+
+    /// Returns the number of animation frames.
+    pub fn frame_count(&self) -> u8 {
+        self.frame_count
+    }
+
     /// Returns the animation data for the sprite, if any.
     pub fn animation(&self) -> Animation {
         self.animation.clone()
     }
+
+    /// Returns the ground sound type of the sprite.
+    pub fn ground_sound(&self) -> u8 {
+        self.ground_sound
+    }
+}
+
+impl<'a> TryRead<'a, FormatVersion> for MapSpriteDefinition {
+    fn try_read(bytes: &'a [u8], version: FormatVersion) -> byte::Result<(Self, usize)> {
+        match version {
+            FormatVersion::V1 => <Self as TryRead<'a, LittleEndian>>::try_read(bytes, LittleEndian),
+            FormatVersion::V0 => Self::read_v0(bytes, LittleEndian),
+        }
+    }
+}
+
+impl MapSpriteDefinition {
+    /// Reads a definition in the hypothetical layout of [`FormatVersion::V0`], which has no
+    /// render size.
+    fn read_v0<C: Endianess>(bytes: &[u8], ctx: C) -> byte::Result<(Self, usize)> {
+        let offset = &mut 0;
+
+        let id = bytes.read(offset, ctx)?;
+        let origin_x = bytes.read(offset, ctx)?;
+        let origin_y = bytes.read(offset, ctx)?;
+        let texture_width = bytes.read(offset, ctx)?;
+        let texture_height = bytes.read(offset, ctx)?;
+        let texture_id = bytes.read(offset, ctx)?;
+        let flags = bytes.read(offset, ctx)?;
+        let visual_height = bytes.read(offset, ctx)?;
+        let visibility_mask = bytes.read(offset, ctx)?;
+        let export_mask = bytes.read(offset, ctx)?;
+        let shader = bytes.read(offset, ctx)?;
+        let frame_count = bytes.read(offset, ctx)?;
+        let animation = bytes.read(offset, (ctx, frame_count))?;
+        let ground_sound = bytes.read(offset, ctx)?;
+
+        Ok((
+            Self {
+                id,
+                origin_x,
+                origin_y,
+                texture_width,
+                texture_height,
+                render_width: texture_width,
+                render_height: texture_height,
+                texture_id,
+                flags,
+                visual_height,
+                visibility_mask,
+                export_mask,
+                shader,
+                frame_count,
+                animation,
+                ground_sound,
+            },
+            *offset,
+        ))
+    }
+}
+
+/// Writes the definition in the layout of the newest revision.
+impl<C: Endianess> TryWrite<C> for &MapSpriteDefinition {
+    fn try_write(self, bytes: &mut [u8], ctx: C) -> byte::Result<usize> {
+        let offset = &mut 0;
+        bytes.write(offset, self.id, ctx)?;
+        bytes.write(offset, self.origin_x, ctx)?;
+        bytes.write(offset, self.origin_y, ctx)?;
+        bytes.write(offset, self.texture_width, ctx)?;
+        bytes.write(offset, self.texture_height, ctx)?;
+        bytes.write(offset, self.render_width, ctx)?;
+        bytes.write(offset, self.render_height, ctx)?;
+        bytes.write(offset, self.texture_id, ctx)?;
+        bytes.write(offset, self.flags, ctx)?;
+        bytes.write(offset, self.visual_height, ctx)?;
+        bytes.write(offset, self.visibility_mask, ctx)?;
+        bytes.write(offset, self.export_mask, ctx)?;
+        bytes.write(offset, self.shader, ctx)?;
+        bytes.write(offset, self.frame_count, ctx)?;
+        bytes.write(offset, &self.animation, (ctx, self.frame_count))?;
+        bytes.write(offset, self.ground_sound, ctx)?;
+        Ok(*offset)
+    }
 }
 
 /// Flags dictating the behavior and properties of a sprite.
@@ -89,6 +207,7 @@ impl MapSpriteDefinition {
 /// - `is_before_mobile`: Represents if the element should be drawn before mobile entities.
 /// - `is_walkable`: Represents if the cell containing this element is walkable.
 #[bitfield(u8)]
+#[derive(PartialEq, Eq)]
 pub struct SpriteFlags {
     #[bits(4)]
     pub slope: u8,
@@ -105,8 +224,30 @@ impl<C> TryRead<'_, C> for SpriteFlags {
     }
 }
 
+impl<C> TryWrite<C> for SpriteFlags {
+    fn try_write(self, bytes: &mut [u8], ctx: C) -> Result<usize, byte::Error> {
+        self.into_bits().try_write(bytes, ctx)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for SpriteFlags {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("SpriteFlags", 5)?;
+        state.serialize_field("slope", &self.slope())?;
+        state.serialize_field("is_flip", &self.is_flip())?;
+        state.serialize_field("is_move_top", &self.is_move_top())?;
+        state.serialize_field("is_before_mobile", &self.is_before_mobile())?;
+        state.serialize_field("is_walkable", &self.is_walkable())?;
+        state.end()
+    }
+}
+
 /// An animation defined for a sprite.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Animation {
     /// The sprite is static.
     None,
@@ -119,13 +260,29 @@ impl<C: Endianess> TryRead<'_, (C, u8)> for Animation {
         if count == 0 {
             return Ok((Self::None, 0));
         }
-        let (animation, offset) = Frames::try_read(bytes, (ctx, count))?;
-        Ok((Self::Frames(Arc::new(animation)), offset))
+        let offset = &mut 0;
+        let animation: Frames = util::read_traced(bytes, offset, (ctx, count), None)?;
+        Ok((Self::Frames(Arc::new(animation)), *offset))
+    }
+}
+
+impl<C: Endianess> TryWrite<(C, u8)> for &Animation {
+    fn try_write(self, bytes: &mut [u8], (ctx, count): (C, u8)) -> Result<usize, byte::Error> {
+        match self {
+            Animation::None if count == 0 => Ok(0),
+            Animation::Frames(frames) if count != 0 => {
+                frames.as_ref().try_write(bytes, (ctx, count))
+            }
+            _ => Err(byte::Error::BadInput {
+                err: "frame count does not match the animation",
+            }),
+        }
     }
 }
 
 /// The frames constituting an animation.
-#[derive(Debug, Default)]
+#[derive(Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Frames {
     /// The total duration of all frames combined.
     total_time: u32,
@@ -159,6 +316,16 @@ impl Frames {
         self.height
     }
 
+    /// Returns the dimensions of the whole sprite sheet.
+    pub fn full_size(&self) -> (u16, u16) {
+        (self.full_width, self.full_height)
+    }
+
+    /// Returns the duration of each frame in milliseconds.
+    pub fn frame_durations(&self) -> &[u16] {
+        &self.frame_durations
+    }
+
     /// Returns an iterator over the individual frames.
     pub fn iter(&self) -> impl ExactSizeIterator<Item = Frame> {
         let mut time = 0;
@@ -206,6 +373,32 @@ impl<C: Endianess> TryRead<'_, (C, u8)> for Frames {
     }
 }
 
+impl<C: Endianess> TryWrite<(C, u8)> for &Frames {
+    fn try_write(self, bytes: &mut [u8], (ctx, count): (C, u8)) -> Result<usize, byte::Error> {
+        if self.frame_durations.len() != count.into() || self.frame_coords.len() != count.into() {
+            return Err(byte::Error::BadInput {
+                err: "frame count does not match the animation",
+            });
+        }
+        let offset = &mut 0;
+
+        bytes.write(offset, self.total_time, ctx)?;
+        bytes.write(offset, self.width, ctx)?;
+        bytes.write(offset, self.height, ctx)?;
+        bytes.write(offset, self.full_width, ctx)?;
+        bytes.write(offset, self.full_height, ctx)?;
+        for &duration in &self.frame_durations {
+            bytes.write(offset, duration, ctx)?;
+        }
+        for &[x, y] in &self.frame_coords {
+            bytes.write(offset, x, ctx)?;
+            bytes.write(offset, y, ctx)?;
+        }
+
+        Ok(*offset)
+    }
+}
+
 /// A single frame of an animation.
 #[derive(Debug)]
 pub struct Frame {
@@ -218,25 +411,69 @@ pub struct Frame {
 }
 
 /// A library of map sprite definitions.
-#[derive(Debug)]
+#[derive(Debug, Default, PartialEq, Eq)]
 pub struct MapSpriteLibrary {
     /// A mapping from a definition ID to the sprite's properties.
     elements: HashMap<i32, MapSpriteDefinition>,
 }
 
 impl MapSpriteLibrary {
-    /// Loads a library from a zip archive.
+    /// Loads a library from a zip archive, detecting the revision of its layout.
     pub fn load<R: Seek + Read>(input: R) -> Result<Self, AssetError> {
+        Self::load_with_version(input, None)
+    }
+
+    /// Loads a library from a zip archive with the given layout revision, or with the detected
+    /// one when it's `None`.
+    pub fn load_with_version<R: Seek + Read>(
+        input: R,
+        version: Option<FormatVersion>,
+    ) -> Result<Self, AssetError> {
         let mut archive = zip::ZipArchive::new(input)?;
         let mut entry = archive.by_name("elements.lib")?;
         let mut bytes = Vec::with_capacity(entry.size() as usize);
         entry.read_to_end(&mut bytes)?;
 
-        let (result, _) = MapSpriteLibrary::try_read(&bytes, LittleEndian)?;
+        let (result, _, _) =
+            FormatVersion::decode_entry::<MapSpriteLibrary>(&bytes, entry.name(), version)?;
         Ok(result)
     }
+
+    /// Saves the library as a zip archive containing a single `elements.lib` entry.
+    pub fn save<W: Write + Seek>(&self, output: W) -> Result<(), AssetError> {
+        let bytes = util::write_to_vec(self, LittleEndian).map_err(AssetError::Encoding)?;
+
+        let mut writer = zip::ZipWriter::new(output);
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        writer.start_file("elements.lib", options)?;
+        writer.write_all(&bytes)?;
+        writer.finish()?;
+        Ok(())
+    }
+
+    /// Returns the definition with the given ID, if any.
+    pub fn get(&self, id: i32) -> Option<&MapSpriteDefinition> {
+        self.elements.get(&id)
+    }
+
+    /// Returns an iterator over the definitions in the library, in no particular order.
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = &MapSpriteDefinition> {
+        self.elements.values()
+    }
+
+    /// Adds a definition to the library, returning the definition it replaces, if any.
+    pub fn insert(&mut self, definition: MapSpriteDefinition) -> Option<MapSpriteDefinition> {
+        self.elements.insert(definition.id, definition)
+    }
+
+    /// Removes the definition with the given ID from the library.
+    pub fn remove(&mut self, id: i32) -> Option<MapSpriteDefinition> {
+        self.elements.remove(&id)
+    }
 }
 
+/// Panics if there's no definition with the given ID, see [`MapSpriteLibrary::get`] for a
+/// fallible lookup.
 impl ops::Index<i32> for MapSpriteLibrary {
     type Output = MapSpriteDefinition;
 
@@ -245,16 +482,59 @@ impl ops::Index<i32> for MapSpriteLibrary {
     }
 }
 
+/// Reads the definitions in the layout of [`FormatVersion::V1`].
 impl<'a, C: Endianess> TryRead<'a, C> for MapSpriteLibrary {
     fn try_read(bytes: &'a [u8], ctx: C) -> byte::Result<(Self, usize)> {
         let offset = &mut 0;
-
         let count: u32 = bytes.read(offset, ctx)?;
-        let mut elements = HashMap::with_capacity(count as usize);
-        for _ in 0..count {
-            let element: MapSpriteDefinition = bytes.read(offset, ctx)?;
-            elements.insert(element.id, element);
-        }
+        let elements = read_definitions(bytes, offset, count, ctx)?;
+        Ok((Self { elements }, *offset))
+    }
+}
+
+/// Reads the definitions in the layout of the given revision.
+impl<'a> TryRead<'a, FormatVersion> for MapSpriteLibrary {
+    fn try_read(bytes: &'a [u8], version: FormatVersion) -> byte::Result<(Self, usize)> {
+        let offset = &mut 0;
+        let count: u32 = bytes.read(offset, LittleEndian)?;
+        let elements = read_definitions(bytes, offset, count, version)?;
         Ok((Self { elements }, *offset))
     }
 }
+
+fn read_definitions<'a, C>(
+    bytes: &'a [u8],
+    offset: &mut usize,
+    count: u32,
+    ctx: C,
+) -> byte::Result<HashMap<i32, MapSpriteDefinition>>
+where
+    C: Copy,
+    MapSpriteDefinition: TryRead<'a, C>,
+{
+    let mut elements = HashMap::with_capacity(count as usize);
+    for index in 0..count as usize {
+        let element: MapSpriteDefinition = util::read_traced(bytes, offset, ctx, Some(index))?;
+        elements.insert(element.id, element);
+    }
+    Ok(elements)
+}
+
+/// Writes the definitions ordered by their ID.
+impl<C: Endianess> TryWrite<C> for &MapSpriteLibrary {
+    fn try_write(self, bytes: &mut [u8], ctx: C) -> byte::Result<usize> {
+        let offset = &mut 0;
+
+        let count = u32::try_from(self.elements.len()).map_err(|_| byte::Error::BadInput {
+            err: "too many sprite definitions",
+        })?;
+        bytes.write(offset, count, ctx)?;
+
+        let mut elements: Vec<_> = self.elements.values().collect();
+        elements.sort_by_key(|element| element.id);
+        for element in elements {
+            bytes.write(offset, element, ctx)?;
+        }
+        Ok(*offset)
+    }
+}
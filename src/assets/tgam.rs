@@ -1,5 +1,18 @@
 use byte::ctx::{Endianess, Len};
-use byte::{BytesExt, TryRead};
+use byte::{BytesExt, TryRead, TryWrite};
+
+use crate::util;
+
+/// The first byte of the header of images with a mask resize factor.
+///
+/// The header is the `TGAM` magic stored backwards, as `mAGT`, where the lower case `m`
+/// (`0x6D`) is what the client checks before reading the mask resize factor.
+const MASK_RESIZE_HEADER: u8 = b'm';
+/// The first byte of the header of images without a mask resize factor.
+///
+/// The client treats any other first byte as having no mask resize factor, so the upper case
+/// `M` (`0x4D`) of the `MAGT` header is written to keep the magic readable.
+const PLAIN_HEADER: u8 = b'M';
 
 /// Represents an image loaded from a TGAM format.
 #[derive(Debug)]
@@ -23,6 +36,63 @@ impl<'a> Tgam<'a> {
         self.rgba
     }
 
+    /// Returns the mask data of the image.
+    #[inline]
+    pub fn mask(&self) -> &'a [u8] {
+        self.mask
+    }
+
+    /// Returns the factor by which the mask is downscaled relative to the image.
+    #[inline]
+    pub fn mask_resize(&self) -> u8 {
+        self.mask_resize
+    }
+
+    /// Returns the dimensions of the mask, which covers the physical size of the image
+    /// downscaled by `mask_resize`.
+    pub fn mask_size(&self) -> (u32, u32) {
+        let resize = u32::from(self.mask_resize.max(1));
+        (
+            u32::from(self.width).div_ceil(resize),
+            u32::from(self.height).div_ceil(resize),
+        )
+    }
+
+    /// Returns the mask bit covering the image pixel at `(x, y)`.
+    ///
+    /// The mask is stored as one bit per downscaled pixel in row-major order, least significant
+    /// bit first. Returns `None` if the image has no mask or the pixel lies outside of it.
+    pub fn mask_bit(&self, x: u32, y: u32) -> Option<bool> {
+        let resize = u32::from(self.mask_resize.max(1));
+        let (mask_width, mask_height) = self.mask_size();
+        let (x, y) = (x / resize, y / resize);
+        if x >= mask_width || y >= mask_height {
+            return None;
+        }
+        let bit = y as usize * mask_width as usize + x as usize;
+        let byte = self.mask.get(bit / 8)?;
+        Some((byte >> (bit % 8)) & 1 != 0)
+    }
+
+    /// Returns whether the image pixel at `(x, y)` is opaque, using the mask if the image has
+    /// one and falling back to the alpha channel otherwise.
+    pub fn is_opaque(&self, x: u32, y: u32) -> bool {
+        if x >= self.width.into() || y >= self.height.into() {
+            return false;
+        }
+        if !self.mask.is_empty() {
+            return self.mask_bit(x, y).unwrap_or(false);
+        }
+        let i = (y as usize * self.width() as usize + x as usize) * 4 + 3;
+        self.rgba.get(i).is_some_and(|&alpha| alpha != 0)
+    }
+
+    /// Returns the physical dimensions of the image, without the power of two padding.
+    #[inline]
+    pub fn physical_size(&self) -> (u16, u16) {
+        (self.width, self.height)
+    }
+
     /// Returns the width of the image, rounded up to the nearest power of two.
     #[inline]
     pub fn width(&self) -> u32 {
@@ -34,6 +104,17 @@ impl<'a> Tgam<'a> {
     pub fn height(&self) -> u32 {
         round_up_to_power_of_two(self.height.into())
     }
+
+    /// Copies the image into an owned buffer.
+    pub fn to_buf(&self) -> TgamBuf {
+        TgamBuf {
+            width: self.width,
+            height: self.height,
+            rgba: self.rgba.to_vec(),
+            mask: self.mask.to_vec(),
+            mask_resize: self.mask_resize,
+        }
+    }
 }
 
 impl<'a, C: Copy + Endianess> TryRead<'a, C> for Tgam<'a> {
@@ -54,7 +135,7 @@ impl<'a, C: Copy + Endianess> TryRead<'a, C> for Tgam<'a> {
         let rgba_size: u32 = bytes.read(offset, ctx)?;
         let mask_size: u32 = bytes.read(offset, ctx)?;
 
-        let mask_resize: u8 = if resize_mask == 0x6D {
+        let mask_resize: u8 = if resize_mask == MASK_RESIZE_HEADER {
             bytes.read(offset, ctx)?
         } else {
             1
@@ -76,6 +157,112 @@ impl<'a, C: Copy + Endianess> TryRead<'a, C> for Tgam<'a> {
     }
 }
 
+/// Writes the mask resize factor only when it's not 1, using the short header otherwise.
+impl<C: Copy + Endianess> TryWrite<C> for &Tgam<'_> {
+    fn try_write(self, bytes: &mut [u8], ctx: C) -> byte::Result<usize> {
+        let offset = &mut 0;
+
+        let has_resize = self.mask_resize != 1;
+        let header = if has_resize {
+            MASK_RESIZE_HEADER
+        } else {
+            PLAIN_HEADER
+        };
+        bytes.write(offset, header, ctx)?;
+        util::write_slice(bytes, offset, b"AGT")?;
+
+        bytes.write(offset, self.width, ctx)?;
+        bytes.write(offset, self.height, ctx)?;
+        bytes.write(offset, len_u32(self.rgba)?, ctx)?;
+        bytes.write(offset, len_u32(self.mask)?, ctx)?;
+        if has_resize {
+            bytes.write(offset, self.mask_resize, ctx)?;
+        }
+
+        util::write_slice(bytes, offset, self.rgba)?;
+        util::write_slice(bytes, offset, self.mask)?;
+        Ok(*offset)
+    }
+}
+
+fn len_u32(data: &[u8]) -> byte::Result<u32> {
+    u32::try_from(data.len()).map_err(|_| byte::Error::BadInput {
+        err: "image data too large",
+    })
+}
+
+/// An owned TGAM image, which can be encoded back into the TGAM format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TgamBuf {
+    /// The physical width of the image.
+    width: u16,
+    /// The physical height of the image.
+    height: u16,
+    /// The pixel data in RGBA format, padded to powers of two.
+    rgba: Vec<u8>,
+    /// The mask data associated with the image.
+    mask: Vec<u8>,
+    /// The factor by which the mask is downscaled relative to the image.
+    mask_resize: u8,
+}
+
+impl TgamBuf {
+    /// Creates an image without a mask from RGBA pixel data covering the physical size
+    /// rounded up to powers of two. Returns `None` if the data has the wrong length.
+    pub fn new(width: u16, height: u16, rgba: Vec<u8>) -> Option<Self> {
+        let padded_width = round_up_to_power_of_two(width.into()) as usize;
+        let padded_height = round_up_to_power_of_two(height.into()) as usize;
+        (rgba.len() == padded_width * padded_height * 4).then_some(Self {
+            width,
+            height,
+            rgba,
+            mask: Vec::new(),
+            mask_resize: 1,
+        })
+    }
+
+    /// Generates the mask from the alpha channel, with a bit for each block of
+    /// `resize` by `resize` pixels that is set when any pixel of the block is opaque.
+    pub fn with_generated_mask(mut self, resize: u8) -> Self {
+        let resize = resize.max(1);
+        self.mask_resize = resize;
+        self.mask = Vec::new();
+
+        let tgam = self.as_tgam();
+        let (mask_width, mask_height) = tgam.mask_size();
+        let stride = tgam.width() as usize;
+        let mut mask = vec![0u8; (mask_width as usize * mask_height as usize).div_ceil(8)];
+        for y in 0..u32::from(self.height) {
+            for x in 0..u32::from(self.width) {
+                let alpha = self.rgba[(y as usize * stride + x as usize) * 4 + 3];
+                if alpha != 0 {
+                    let (x, y) = (x / u32::from(resize), y / u32::from(resize));
+                    let bit = y as usize * mask_width as usize + x as usize;
+                    mask[bit / 8] |= 1 << (bit % 8);
+                }
+            }
+        }
+        self.mask = mask;
+        self
+    }
+
+    /// Returns a view of the image that borrows the buffer.
+    pub fn as_tgam(&self) -> Tgam<'_> {
+        Tgam {
+            width: self.width,
+            height: self.height,
+            rgba: &self.rgba,
+            mask: &self.mask,
+            mask_resize: self.mask_resize,
+        }
+    }
+
+    /// Encodes the image in the TGAM format.
+    pub fn encode(&self) -> byte::Result<Vec<u8>> {
+        util::write_to_vec(&self.as_tgam(), byte::ctx::LittleEndian)
+    }
+}
+
 fn round_up_to_power_of_two(value: u32) -> u32 {
     if value < 2 {
         return value;
@@ -88,3 +275,106 @@ fn round_up_to_power_of_two(value: u32) -> u32 {
     v = v | v >> 16;
     v + 1
 }
+
+#[cfg(test)]
+mod tests {
+    use byte::ctx::LittleEndian;
+
+    use super::*;
+
+    /// A 5x3 image with a mask downscaled by 2, covering 3x2 blocks of which the top-left and
+    /// the bottom-right ones are opaque.
+    fn masked() -> Tgam<'static> {
+        Tgam {
+            width: 5,
+            height: 3,
+            rgba: &[],
+            mask: &[0b0010_0001],
+            mask_resize: 2,
+        }
+    }
+
+    #[test]
+    fn mask_size_rounds_up_partial_blocks() {
+        assert_eq!(masked().mask_size(), (3, 2));
+
+        let unscaled = Tgam {
+            mask_resize: 0,
+            ..masked()
+        };
+        assert_eq!(unscaled.mask_size(), (5, 3));
+    }
+
+    #[test]
+    fn mask_bit_covers_blocks_of_pixels() {
+        let tgam = masked();
+        assert_eq!(tgam.mask_bit(0, 0), Some(true));
+        assert_eq!(tgam.mask_bit(1, 1), Some(true));
+        assert_eq!(tgam.mask_bit(2, 0), Some(false));
+        assert_eq!(tgam.mask_bit(3, 1), Some(false));
+        // the last block is only half covered by the image
+        assert_eq!(tgam.mask_bit(4, 2), Some(true));
+        assert_eq!(tgam.mask_bit(5, 3), Some(true));
+        assert_eq!(tgam.mask_bit(6, 0), None);
+        assert_eq!(tgam.mask_bit(0, 4), None);
+
+        let unmasked = Tgam {
+            mask: &[],
+            ..masked()
+        };
+        assert_eq!(unmasked.mask_bit(0, 0), None);
+    }
+
+    #[test]
+    fn pixels_outside_of_the_image_are_transparent() {
+        let tgam = masked();
+        assert!(tgam.is_opaque(4, 2));
+        assert!(!tgam.is_opaque(5, 2));
+        assert!(!tgam.is_opaque(4, 3));
+    }
+
+    /// A 3x3 image, padded to 4x4, whose left column and bottom-right pixel are opaque.
+    fn image() -> TgamBuf {
+        let mut rgba = vec![0u8; 4 * 4 * 4];
+        for (i, pixel) in rgba.chunks_exact_mut(4).enumerate() {
+            pixel.copy_from_slice(&[i as u8, 2 * i as u8, 255 - i as u8, 0]);
+        }
+        for (x, y) in [(0, 0), (0, 1), (0, 2), (2, 2)] {
+            rgba[(y * 4 + x) * 4 + 3] = 255;
+        }
+        TgamBuf::new(3, 3, rgba).unwrap()
+    }
+
+    #[test]
+    fn encoded_images_decode_to_the_same_image() {
+        for resize in [1, 2, 3] {
+            let image = image().with_generated_mask(resize);
+            let bytes = image.encode().unwrap();
+            let header = if resize == 1 { b"MAGT" } else { b"mAGT" };
+            assert_eq!(&bytes[..4], header);
+
+            let offset = &mut 0;
+            let tgam: Tgam = bytes.read_with(offset, LittleEndian).unwrap();
+            assert_eq!(*offset, bytes.len());
+            assert_eq!(tgam.to_buf(), image);
+            assert_eq!(tgam.mask_resize(), resize);
+        }
+    }
+
+    #[test]
+    fn generated_masks_cover_the_opaque_pixels() {
+        let image = image().with_generated_mask(1);
+        let tgam = image.as_tgam();
+        assert_eq!(tgam.mask(), &[0b0100_1001, 0b0000_0001]);
+        for y in 0..3 {
+            for x in 0..3 {
+                let opaque = x == 0 || (x, y) == (2, 2);
+                assert_eq!(tgam.mask_bit(x, y), Some(opaque), "({x}, {y})");
+            }
+        }
+
+        // the 2x2 blocks are opaque when any of their pixels is
+        let image = image.with_generated_mask(2);
+        assert_eq!(image.as_tgam().mask(), &[0b0000_1101]);
+    }
+}
@@ -0,0 +1,170 @@
+use std::sync::Arc;
+
+use bevy::prelude::*;
+use bevy::tasks::futures_lite::future;
+use bevy::tasks::{AsyncComputeTaskPool, Task, block_on};
+use bevy_egui::{EguiContexts, egui};
+
+use crate::assets::{AssetError, MapSpriteLibrary};
+use crate::game::GameMaps;
+use crate::inspector::Inspector;
+use crate::render::MapRenderer;
+use crate::streaming::{ChunkStreamer, MapSource};
+
+/// Lists the maps of the game and loads the one picked in the UI in the background.
+#[derive(Resource)]
+pub struct MapBrowser {
+    maps: GameMaps,
+    map_ids: Vec<i32>,
+    sprites: Arc<MapSpriteLibrary>,
+    filter: String,
+    current: Option<i32>,
+    /// The map to load once the task pools are running, requested before the app started.
+    pending: Option<i32>,
+    loading: Option<(i32, Task<Result<MapSource, AssetError>>)>,
+    error: Option<String>,
+}
+
+impl MapBrowser {
+    /// Lists the available maps and loads the sprite library they share.
+    pub fn new(maps: GameMaps) -> Result<Self, AssetError> {
+        Ok(Self {
+            map_ids: maps.map_ids()?,
+            sprites: Arc::new(maps.load_library()?),
+            maps,
+            filter: String::new(),
+            current: None,
+            pending: None,
+            loading: None,
+            error: None,
+        })
+    }
+
+    /// Requests a map to be loaded by [`map_loading_system`] on its next run.
+    ///
+    /// Unlike [`MapBrowser::load`], this can be called before the app is running.
+    pub fn request(&mut self, map_id: i32) {
+        self.pending = Some(map_id);
+    }
+
+    /// Starts loading a map, replacing the current one once it's ready.
+    ///
+    /// A load that's still in progress is cancelled. This needs the task pools of a running app,
+    /// see [`MapBrowser::request`] otherwise.
+    pub fn load(&mut self, map_id: i32) {
+        self.pending = None;
+        let maps = self.maps.clone();
+        let sprites = self.sprites.clone();
+        let task = AsyncComputeTaskPool::get()
+            .spawn(async move { MapSource::open(&maps, map_id, sprites) });
+        self.loading = Some((map_id, task));
+        self.error = None;
+    }
+}
+
+/// Starts loading the requested map and streams it once its chunks are indexed.
+pub fn map_loading_system(
+    mut commands: Commands<'_, '_>,
+    mut browser: ResMut<'_, MapBrowser>,
+    mut renderer: ResMut<'_, MapRenderer>,
+    mut streamer: ResMut<'_, ChunkStreamer>,
+    mut inspector: ResMut<'_, Inspector>,
+) {
+    if let Some(map_id) = browser.pending {
+        browser.load(map_id);
+    }
+    let Some((map_id, task)) = browser.loading.as_mut() else {
+        return;
+    };
+    let map_id = *map_id;
+    let Some(result) = block_on(future::poll_once(task)) else {
+        return;
+    };
+    browser.loading = None;
+
+    match result {
+        Ok(source) => {
+            renderer.clear(&mut commands);
+            streamer.set_source(source);
+            inspector.clear_selection();
+            browser.current = Some(map_id);
+        }
+        Err(err) => browser.error = Some(format!("could not load map {map_id}: {err}")),
+    }
+}
+
+pub fn browser_ui_system(
+    mut contexts: EguiContexts<'_, '_>,
+    mut browser: ResMut<'_, MapBrowser>,
+    streamer: Res<'_, ChunkStreamer>,
+    renderer: Res<'_, MapRenderer>,
+) {
+    let browser = &mut *browser;
+    let mut picked = None;
+
+    egui::Window::new("Maps")
+        .anchor(egui::Align2::RIGHT_TOP, egui::vec2(-8., 8.))
+        .show(contexts.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Filter");
+                ui.text_edit_singleline(&mut browser.filter);
+            });
+            if let Some((map_id, _)) = &browser.loading {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label(format!("Loading map {map_id}..."));
+                });
+            } else if let Some(error) = &browser.error {
+                ui.colored_label(ui.visuals().error_fg_color, error);
+            } else if streamer.pending() > 0 {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label(format!("Loading {} chunks...", streamer.pending()));
+                });
+            }
+
+            let problems = renderer.problems();
+            if !problems.is_empty() {
+                egui::CollapsingHeader::new(format!("Problems ({})", problems.len()))
+                    .id_salt("problems")
+                    .show(ui, |ui| {
+                        egui::ScrollArea::vertical()
+                            .id_salt("problem list")
+                            .max_height(200.)
+                            .show(ui, |ui| {
+                                for (definition_id, cells) in problems.unknown_definitions() {
+                                    let (x, y, z) = cells.first().copied().unwrap_or_default();
+                                    ui.label(format!(
+                                        "Missing definition {definition_id}: {} elements, \
+                                         first at {x}, {y}, {z}",
+                                        cells.len()
+                                    ));
+                                }
+                                for (texture_id, err) in problems.failed_textures() {
+                                    ui.label(format!("Failed texture {texture_id}: {err}"));
+                                }
+                            });
+                    });
+            }
+
+            ui.separator();
+            egui::ScrollArea::vertical()
+                .max_height(400.)
+                .show(ui, |ui| {
+                    let filter = browser.filter.trim();
+                    for &map_id in &browser.map_ids {
+                        if !map_id.to_string().contains(filter) {
+                            continue;
+                        }
+                        let selected = browser.current == Some(map_id);
+                        if ui.selectable_label(selected, map_id.to_string()).clicked() {
+                            picked = Some(map_id);
+                        }
+                    }
+                });
+        });
+
+    if let Some(map_id) = picked {
+        browser.load(map_id);
+    }
+}
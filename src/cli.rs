@@ -0,0 +1,275 @@
+use std::path::PathBuf;
+
+use anyhow::bail;
+use pico_args::Arguments;
+use vakfu::assets::FormatVersion;
+use vakfu::game::{self, GameMaps};
+
+/// An error in the arguments given to a command, reported along with a hint about the usage.
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct UsageError(pub String);
+
+/// A command of the binary along with its help text.
+pub struct Command {
+    pub name: &'static str,
+    pub summary: &'static str,
+    pub help: &'static str,
+}
+
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "view",
+        summary: "browse the maps in a window (default)",
+        help: "\
+USAGE:
+  vakfu view [--path <GAME>] [--map <ID>]
+
+OPTIONS:
+  --path <GAME>  the installation directory of the game
+  --map <ID>     the map to open at startup",
+    },
+    Command {
+        name: "render",
+        summary: "render a map to a PNG without opening a window",
+        help: "\
+USAGE:
+  vakfu render [--path <GAME>] --map <ID> --out <FILE> [OPTIONS]
+
+OPTIONS:
+  --path <GAME>     the installation directory of the game
+  --map <ID>        the map to render, the available maps are listed when missing
+  --out <FILE>      the PNG to write
+  --scale <FACTOR>  the scale of the image relative to the map, 1 by default
+  --no-light        render without the lighting of the map
+  --time <HOURS>    the hour of the day to light the map at, full daylight by default",
+    },
+    Command {
+        name: "list-maps",
+        summary: "list the IDs of the maps of the game",
+        help: "\
+USAGE:
+  vakfu list-maps [--path <GAME>] [--details]
+
+OPTIONS:
+  --path <GAME>  the installation directory of the game
+  --details      summarize the content of every map",
+    },
+    Command {
+        name: "info",
+        summary: "summarize the content of a map",
+        help: "\
+USAGE:
+  vakfu info [--path <GAME>] --map <ID>
+
+OPTIONS:
+  --path <GAME>  the installation directory of the game
+  --map <ID>     the map to summarize",
+    },
+    Command {
+        name: "validate",
+        summary: "check that the game data decodes and its references resolve",
+        help: "\
+USAGE:
+  vakfu validate [--path <GAME>]
+
+Decodes every map, light map, the sprite library and the textures, reporting the entries that
+fail to decode along with the byte offset of the failure, elements referring to definitions
+missing from the library and definitions referring to missing textures. Exits with status 1
+when any problem is found.
+
+OPTIONS:
+  --path <GAME>  the installation directory of the game",
+    },
+    Command {
+        name: "dump-map",
+        summary: "dump the decoded content of a map as JSON or RON",
+        help: "\
+USAGE:
+  vakfu dump-map [--path <GAME>] --map <ID> [--format json|ron] [--out <FILE>]
+
+OPTIONS:
+  --path <GAME>      the installation directory of the game
+  --map <ID>         the map to dump
+  --format <FORMAT>  json (default) or ron
+  --out <FILE>       the file to write, the standard output by default",
+    },
+    Command {
+        name: "elements",
+        summary: "query the sprite library",
+        help: "\
+USAGE:
+  vakfu elements list [--path <GAME>] [FILTERS] [--json]
+  vakfu elements show [--path <GAME>] <ID> [--json]
+
+OPTIONS:
+  --path <GAME>  the installation directory of the game
+  --json         print JSON instead of a table
+
+FILTERS:
+  --texture <ID>            the texture used by the element
+  --shader <N>              the shader of the element
+  --visibility-mask <BITS>  bits set in the visibility mask
+  --export-mask <BITS>      bits set in the export mask
+  --ground-sound <N>        the ground sound of the element
+  --slope <N>               the slope of the element
+  --sloped <true|false>     whether the slope is not zero
+  --flip <true|false>       whether the texture is flipped
+  --move-top <true|false>   whether the element is moved to the top
+  --before-mobile <true|false>
+                            whether the element is drawn before mobiles
+  --walkable <true|false>   whether the element is walkable
+  --animated <true|false>   whether the element is animated",
+    },
+    Command {
+        name: "export-animation",
+        summary: "export the animation of an element as an APNG or a sprite sheet",
+        help: "\
+USAGE:
+  vakfu export-animation [--path <GAME>] --element <ID> --out <FILE> [--sheet]
+
+OPTIONS:
+  --path <GAME>    the installation directory of the game
+  --element <ID>   the definition ID of the animated element
+  --out <FILE>     the PNG to write
+  --sheet          write the frames side by side with their timings in <FILE>.json",
+    },
+    Command {
+        name: "tgam2png",
+        summary: "convert a TGAM texture to a PNG",
+        help: "\
+USAGE:
+  vakfu tgam2png <INPUT> <OUTPUT>
+  vakfu tgam2png [--path <GAME>] --texture <ID> <OUTPUT>
+
+OPTIONS:
+  --path <GAME>    the installation directory of the game
+  --texture <ID>   convert a texture of the game instead of a file",
+    },
+    Command {
+        name: "png2tgam",
+        summary: "convert a PNG to a TGAM texture",
+        help: "\
+USAGE:
+  vakfu png2tgam [--mask | --mask-resize <N>] <INPUT> <OUTPUT>
+
+OPTIONS:
+  --mask             generate a mask from the alpha channel
+  --mask-resize <N>  generate a mask downscaled by the given factor",
+    },
+    Command {
+        name: "extract-textures",
+        summary: "extract every texture of the game to PNGs",
+        help: "\
+USAGE:
+  vakfu extract-textures [--path <GAME>] --out <DIR> [--mask]
+
+OPTIONS:
+  --path <GAME>  the installation directory of the game
+  --out <DIR>    the directory to write the PNGs to
+  --mask         also write the masks of the textures",
+    },
+];
+
+/// Returns the command with the given name.
+pub fn command(name: &str) -> Option<&'static Command> {
+    COMMANDS.iter().find(|command| command.name == name)
+}
+
+pub fn print_help() {
+    println!("vakfu - a viewer and toolkit for the maps of Wakfu\n");
+    println!("USAGE:\n  vakfu [COMMAND] [OPTIONS]\n");
+    println!("COMMANDS:");
+    for command in COMMANDS {
+        println!("  {:<18} {}", command.name, command.summary);
+    }
+    println!("\nRun `vakfu <COMMAND> --help` for the options of a command.");
+    println!(
+        "\nWithout --path, the game is located through the {} environment variable, the \
+         game_path entry of $XDG_CONFIG_HOME/vakfu/config, or the usual install locations of the \
+         Ankama launcher and Steam.",
+        game::GAME_PATH_VAR
+    );
+    println!(
+        "\nThe layout revision of the sprite library is detected when it's read, \
+         --format-version <{}> forces one instead.",
+        FormatVersion::ALL
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("|")
+    );
+}
+
+pub fn print_command_help(command: &Command) {
+    println!(
+        "vakfu {} - {}\n\n{}",
+        command.name, command.summary, command.help
+    );
+}
+
+/// Fails if any arguments were not consumed by the command, which usually means a typo.
+pub fn finish(pargs: &mut Arguments) -> anyhow::Result<()> {
+    let unused = std::mem::replace(pargs, Arguments::from_vec(Vec::new())).finish();
+    if !unused.is_empty() {
+        let unused: Vec<_> = unused.iter().map(|arg| arg.to_string_lossy()).collect();
+        bail!(UsageError(format!(
+            "unexpected arguments: {}",
+            unused.join(" ")
+        )));
+    }
+    Ok(())
+}
+
+/// Returns the maps of the game installation given with `--path`, or of the first one found
+/// by [`game::discover_install`] otherwise, decoded with the revision forced by
+/// `--format-version` if any.
+pub fn game_maps(pargs: &mut Arguments) -> anyhow::Result<GameMaps> {
+    let maps = find_game_maps(pargs)?;
+    let version: Option<FormatVersion> = pargs.opt_value_from_str("--format-version")?;
+    Ok(match version {
+        Some(version) => maps.with_format_version(version),
+        None => maps,
+    })
+}
+
+fn find_game_maps(pargs: &mut Arguments) -> anyhow::Result<GameMaps> {
+    if let Some(game_path) = pargs.opt_value_from_str::<_, PathBuf>("--path")? {
+        return Ok(GameMaps::new(game_path));
+    }
+    let Some(found) = game::discover_install() else {
+        let candidates: Vec<_> = game::install_candidates()
+            .iter()
+            .map(|candidate| format!("  {} ({})", candidate.path.display(), candidate.source))
+            .collect();
+        bail!(UsageError(format!(
+            "could not find the game, pass --path or set {}, the following locations were tried:\n{}",
+            game::GAME_PATH_VAR,
+            candidates.join("\n")
+        )));
+    };
+    eprintln!(
+        "using the game at {} (found through the {})",
+        found.path.display(),
+        found.source
+    );
+    Ok(GameMaps::new(found.path))
+}
+
+/// Returns the given map ID, or fails with a list of the available maps.
+pub fn require_map(maps: &GameMaps, map_id: Option<i32>) -> anyhow::Result<i32> {
+    if let Some(map_id) = map_id {
+        return Ok(map_id);
+    }
+    match maps.map_ids() {
+        Ok(map_ids) => bail!(UsageError(format!(
+            "no map specified, you can try one of the following:\n{}",
+            map_ids
+                .iter()
+                .map(i32::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))),
+        Err(err) => bail!("no map specified and could not list the maps ({err})"),
+    }
+}
@@ -0,0 +1,54 @@
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::bail;
+use ron::ser::PrettyConfig;
+use vakfu::game::GameMaps;
+
+/// The formats a map can be dumped as.
+#[derive(Debug, Clone, Copy)]
+pub enum DumpFormat {
+    Json,
+    Ron,
+}
+
+impl FromStr for DumpFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "ron" => Ok(Self::Ron),
+            _ => bail!("unknown format '{s}', expected 'json' or 'ron'"),
+        }
+    }
+}
+
+/// Writes the decoded content of a map, with the elements of every chunk resolved, to a file
+/// or to the standard output.
+pub fn dump_map(
+    maps: &GameMaps,
+    map_id: i32,
+    format: DumpFormat,
+    out: Option<&Path>,
+) -> anyhow::Result<()> {
+    let map = maps.load_map(map_id)?;
+    let dump = match format {
+        DumpFormat::Json => serde_json::to_string_pretty(&map)?,
+        DumpFormat::Ron => ron::ser::to_string_pretty(&map, PrettyConfig::default())?,
+    };
+
+    match out {
+        Some(out) => {
+            fs::write(out, dump)?;
+            eprintln!(
+                "dumped {} chunks of map {map_id} to {}",
+                map.chunks().len(),
+                out.display()
+            );
+        }
+        None => println!("{dump}"),
+    }
+    Ok(())
+}
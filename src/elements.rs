@@ -0,0 +1,160 @@
+use anyhow::bail;
+use pico_args::Arguments;
+use vakfu::assets::MapSpriteDefinition;
+
+use crate::cli::{self, UsageError};
+
+/// Runs one of the `elements` subcommands, which query the sprite library.
+pub fn elements(pargs: &mut Arguments) -> anyhow::Result<()> {
+    match pargs.subcommand()?.as_deref() {
+        Some("list") => list_elements(pargs),
+        Some("show") => show_element(pargs),
+        Some(command) => bail!(UsageError(format!("unknown elements command '{command}'"))),
+        None => bail!(UsageError(
+            "missing elements command, expected 'list' or 'show'".to_owned()
+        )),
+    }
+}
+
+/// Lists the definitions of the library matching all of the given filters, ordered by ID.
+fn list_elements(pargs: &mut Arguments) -> anyhow::Result<()> {
+    let maps = cli::game_maps(pargs)?;
+    let json = pargs.contains("--json");
+    let filter = ElementFilter::from_args(pargs)?;
+    cli::finish(pargs)?;
+
+    let sprites = maps.load_library()?;
+    let mut definitions: Vec<_> = sprites.iter().filter(|def| filter.matches(def)).collect();
+    definitions.sort_unstable_by_key(|def| def.id());
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&definitions)?);
+    } else {
+        print_table(&definitions);
+        println!("{} of {} elements", definitions.len(), sprites.iter().len());
+    }
+    Ok(())
+}
+
+/// Prints a single definition of the library.
+fn show_element(pargs: &mut Arguments) -> anyhow::Result<()> {
+    let maps = cli::game_maps(pargs)?;
+    let json = pargs.contains("--json");
+    let definition_id: i32 = pargs.free_from_str()?;
+    cli::finish(pargs)?;
+
+    let sprites = maps.load_library()?;
+    let Some(definition) = sprites.get(definition_id) else {
+        bail!("element {definition_id} does not exist");
+    };
+    if json {
+        println!("{}", serde_json::to_string_pretty(definition)?);
+    } else {
+        print_table(&[definition]);
+    }
+    Ok(())
+}
+
+fn print_table(definitions: &[&MapSpriteDefinition]) {
+    println!(
+        "{:>10} {:>10} {:>9} {:>5} {:>5} {:>6} {:>4} {:>4} {:>5} {:>6}",
+        "id", "texture", "size", "slope", "flags", "shader", "vis", "exp", "sound", "frames"
+    );
+    for def in definitions {
+        let (width, height) = def.size();
+        let flags = def.flags();
+        let flag_chars: String = [
+            (flags.is_flip(), 'F'),
+            (flags.is_move_top(), 'T'),
+            (flags.is_before_mobile(), 'B'),
+            (flags.is_walkable(), 'W'),
+        ]
+        .iter()
+        .map(|&(set, c)| if set { c } else { '-' })
+        .collect();
+        println!(
+            "{:>10} {:>10} {:>9} {:>5} {:>5} {:>6} {:>#4x} {:>#4x} {:>5} {:>6}",
+            def.id(),
+            def.texture_id(),
+            format!("{width}x{height}"),
+            flags.slope(),
+            flag_chars,
+            def.shader(),
+            def.visibility_mask(),
+            def.export_mask(),
+            def.ground_sound(),
+            def.frame_count()
+        );
+    }
+}
+
+/// Filters on the properties of sprite definitions, all of which have to match.
+///
+/// The masks match when all of their bits are set in the definition, the boolean filters take
+/// `true` or `false`.
+#[derive(Debug)]
+struct ElementFilter {
+    texture_id: Option<i32>,
+    shader: Option<u8>,
+    visibility_mask: Option<u8>,
+    export_mask: Option<u8>,
+    ground_sound: Option<u8>,
+    slope: Option<u8>,
+    sloped: Option<bool>,
+    flip: Option<bool>,
+    move_top: Option<bool>,
+    before_mobile: Option<bool>,
+    walkable: Option<bool>,
+    animated: Option<bool>,
+}
+
+impl ElementFilter {
+    fn from_args(pargs: &mut Arguments) -> anyhow::Result<Self> {
+        Ok(Self {
+            texture_id: pargs.opt_value_from_str("--texture")?,
+            shader: pargs.opt_value_from_str("--shader")?,
+            visibility_mask: pargs.opt_value_from_str("--visibility-mask")?,
+            export_mask: pargs.opt_value_from_str("--export-mask")?,
+            ground_sound: pargs.opt_value_from_str("--ground-sound")?,
+            slope: pargs.opt_value_from_str("--slope")?,
+            sloped: pargs.opt_value_from_str("--sloped")?,
+            flip: pargs.opt_value_from_str("--flip")?,
+            move_top: pargs.opt_value_from_str("--move-top")?,
+            before_mobile: pargs.opt_value_from_str("--before-mobile")?,
+            walkable: pargs.opt_value_from_str("--walkable")?,
+            animated: pargs.opt_value_from_str("--animated")?,
+        })
+    }
+
+    fn matches(&self, def: &MapSpriteDefinition) -> bool {
+        let flags = def.flags();
+        self.texture_id.is_none_or(|id| def.texture_id() == id)
+            && self.shader.is_none_or(|shader| def.shader() == shader)
+            && self
+                .visibility_mask
+                .is_none_or(|mask| def.visibility_mask() & mask == mask)
+            && self
+                .export_mask
+                .is_none_or(|mask| def.export_mask() & mask == mask)
+            && self
+                .ground_sound
+                .is_none_or(|sound| def.ground_sound() == sound)
+            && self.slope.is_none_or(|slope| flags.slope() == slope)
+            && self
+                .sloped
+                .is_none_or(|sloped| (flags.slope() != 0) == sloped)
+            && self.flip.is_none_or(|flip| flags.is_flip() == flip)
+            && self
+                .move_top
+                .is_none_or(|move_top| flags.is_move_top() == move_top)
+            && self
+                .before_mobile
+                .is_none_or(|before| flags.is_before_mobile() == before)
+            && self
+                .walkable
+                .is_none_or(|walkable| flags.is_walkable() == walkable)
+            && self
+                .animated
+                .is_none_or(|animated| (def.frame_count() != 0) == animated)
+    }
+}
@@ -0,0 +1,100 @@
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::assets::{AssetError, FormatVersion, LightMap, Map, MapSpriteLibrary};
+
+mod install;
+
+pub use install::{
+    GAME_PATH_VAR, GameInstall, InstallSource, config_path, discover_install, install_candidates,
+    is_game_root, parse_library_folders,
+};
+
+/// The map data of a Wakfu installation, found in its `contents/maps` directory.
+#[derive(Debug, Clone)]
+pub struct GameMaps {
+    path: PathBuf,
+    format_version: Option<FormatVersion>,
+}
+
+impl GameMaps {
+    /// Creates the map data for the game installed at the given path.
+    pub fn new(game_path: impl AsRef<Path>) -> Self {
+        Self {
+            path: game_path.as_ref().join("contents").join("maps"),
+            format_version: None,
+        }
+    }
+
+    /// Decodes the sprite library with the given layout revision instead of detecting it for
+    /// every definition.
+    pub fn with_format_version(mut self, version: FormatVersion) -> Self {
+        self.format_version = Some(version);
+        self
+    }
+
+    /// Returns the layout revision the sprite library is decoded with, or `None` if it's
+    /// detected.
+    pub fn format_version(&self) -> Option<FormatVersion> {
+        self.format_version
+    }
+
+    /// Returns the path of the map data directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the path of the archive holding the textures of all maps.
+    pub fn textures_path(&self) -> PathBuf {
+        self.path.join("gfx.jar")
+    }
+
+    /// Returns the path of the archive holding the sprite library.
+    pub fn library_path(&self) -> PathBuf {
+        self.path.join("data.jar")
+    }
+
+    /// Returns the path of the archive holding the chunks of a map.
+    pub fn map_path(&self, map_id: i32) -> PathBuf {
+        self.path.join("gfx").join(format!("{map_id}.jar"))
+    }
+
+    /// Returns the path of the archive holding the light map of a map.
+    pub fn light_path(&self, map_id: i32) -> PathBuf {
+        self.path.join("light").join(format!("{map_id}.jar"))
+    }
+
+    /// Returns the IDs of all available maps in ascending order.
+    pub fn map_ids(&self) -> io::Result<Vec<i32>> {
+        let mut map_ids: Vec<i32> = fs::read_dir(self.path.join("gfx"))?
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|entry| entry.extension() == Some("jar".as_ref()))
+            .filter_map(|entry| entry.file_stem()?.to_str()?.parse().ok())
+            .collect();
+        map_ids.sort_unstable();
+
+        Ok(map_ids)
+    }
+
+    /// Loads the sprite library shared by all maps.
+    pub fn load_library(&self) -> Result<MapSpriteLibrary, AssetError> {
+        MapSpriteLibrary::load_with_version(File::open(self.library_path())?, self.format_version)
+    }
+
+    /// Loads the chunks of a map.
+    pub fn load_map(&self, map_id: i32) -> Result<Map, AssetError> {
+        Map::load(File::open(self.map_path(map_id))?)
+    }
+
+    /// Loads the light map of a map, or an empty one if the map has no lighting.
+    pub fn load_light_map(&self, map_id: i32) -> Result<LightMap, AssetError> {
+        let light_path = self.light_path(map_id);
+        if light_path.exists() {
+            LightMap::load(File::open(light_path)?)
+        } else {
+            Ok(LightMap::default())
+        }
+    }
+}
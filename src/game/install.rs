@@ -0,0 +1,265 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use std::{fmt, fs, iter};
+
+/// The environment variable pointing at the game installation.
+pub const GAME_PATH_VAR: &str = "VAKFU_GAME_PATH";
+
+/// The Steam application directory of the game.
+const STEAM_APP_DIR: &str = "Wakfu";
+
+/// Where a candidate location of the game comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstallSource {
+    /// The [`GAME_PATH_VAR`] environment variable.
+    Environment,
+    /// The `game_path` entry of the configuration file.
+    ConfigFile,
+    /// The default directory of the Ankama launcher.
+    AnkamaLauncher,
+    /// One of the Steam library folders.
+    Steam,
+    /// The XDG data directory of the user.
+    XdgData,
+}
+
+impl fmt::Display for InstallSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Environment => "environment variable",
+            Self::ConfigFile => "config file",
+            Self::AnkamaLauncher => "Ankama launcher",
+            Self::Steam => "Steam library",
+            Self::XdgData => "XDG data directory",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A candidate location of a game installation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameInstall {
+    pub path: PathBuf,
+    pub source: InstallSource,
+}
+
+impl GameInstall {
+    /// Creates a candidate location found through the given source.
+    pub fn new(path: PathBuf, source: InstallSource) -> Self {
+        Self { path, source }
+    }
+
+    /// Returns whether the location holds the map data of the game.
+    pub fn is_valid(&self) -> bool {
+        is_game_root(&self.path)
+    }
+}
+
+/// Returns whether the directory is the root of a game installation, containing `contents/maps`.
+pub fn is_game_root(path: &Path) -> bool {
+    path.join("contents").join("maps").is_dir()
+}
+
+/// Returns the first valid location of the game, looking at the candidates returned by
+/// [`install_candidates`] in order.
+pub fn discover_install() -> Option<GameInstall> {
+    install_candidates().into_iter().find(GameInstall::is_valid)
+}
+
+/// Returns the locations where the game may be installed, from the most to the least specific.
+///
+/// The environment variable and the configuration file come first, followed by the default
+/// directories of the Ankama launcher, the Steam library folders and the XDG data directory.
+pub fn install_candidates() -> Vec<GameInstall> {
+    let mut candidates = vec![];
+    if let Some(path) = env_path(GAME_PATH_VAR) {
+        candidates.push(GameInstall::new(path, InstallSource::Environment));
+    }
+    if let Some(path) = config_path().and_then(|path| read_config_game_path(&path)) {
+        candidates.push(GameInstall::new(path, InstallSource::ConfigFile));
+    }
+    if let Some(home) = env_path("HOME") {
+        candidates.extend(default_candidates(&home));
+    }
+    candidates
+}
+
+/// Returns the default install locations within the home directory.
+fn default_candidates(home: &Path) -> Vec<GameInstall> {
+    let config_home = env_path("XDG_CONFIG_HOME").unwrap_or_else(|| home.join(".config"));
+    let data_home = env_path("XDG_DATA_HOME").unwrap_or_else(|| home.join(".local/share"));
+
+    let mut candidates = vec![
+        GameInstall::new(
+            config_home.join("Ankama/zaap/wakfu"),
+            InstallSource::AnkamaLauncher,
+        ),
+        GameInstall::new(home.join("Ankama/Wakfu"), InstallSource::AnkamaLauncher),
+    ];
+
+    let steam_roots = [
+        data_home.join("Steam"),
+        home.join(".steam/steam"),
+        home.join(".var/app/com.valvesoftware.Steam/.local/share/Steam"),
+    ];
+    let mut libraries: Vec<PathBuf> = vec![];
+    for root in steam_roots {
+        let folders = fs::read_to_string(root.join("steamapps/libraryfolders.vdf"))
+            .map(|vdf| parse_library_folders(&vdf))
+            .unwrap_or_default();
+        for library in iter::once(root).chain(folders) {
+            if !libraries.contains(&library) {
+                libraries.push(library);
+            }
+        }
+    }
+    candidates.extend(libraries.into_iter().map(|library| {
+        GameInstall::new(
+            library.join("steamapps/common").join(STEAM_APP_DIR),
+            InstallSource::Steam,
+        )
+    }));
+
+    candidates.push(GameInstall::new(
+        data_home.join("Ankama/Wakfu"),
+        InstallSource::XdgData,
+    ));
+    candidates
+}
+
+/// Returns the path of the configuration file, `$XDG_CONFIG_HOME/vakfu/config`.
+pub fn config_path() -> Option<PathBuf> {
+    let config_home = env_path("XDG_CONFIG_HOME")
+        .or_else(|| env_path("HOME").map(|home| home.join(".config")))?;
+    Some(config_home.join("vakfu").join("config"))
+}
+
+/// Reads the `game_path` entry of a configuration file, see [`parse_config_game_path`].
+fn read_config_game_path(path: &Path) -> Option<PathBuf> {
+    parse_config_game_path(&fs::read_to_string(path).ok()?)
+}
+
+/// Extracts the `game_path = <path>` entry from the contents of a configuration file, ignoring
+/// blank lines and comments starting with `#`.
+fn parse_config_game_path(config: &str) -> Option<PathBuf> {
+    config
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .find(|(key, _)| key.trim() == "game_path")
+        .map(|(_, value)| PathBuf::from(value.trim().trim_matches('"')))
+}
+
+/// Extracts the library paths from the contents of a Steam `libraryfolders.vdf` file.
+///
+/// Only the `"path"` entries are of interest, so the file is not parsed as a whole. Lines that
+/// hold anything else than the quoted key and value, such as an unterminated value, are skipped.
+pub fn parse_library_folders(vdf: &str) -> Vec<PathBuf> {
+    vdf.lines()
+        .filter_map(|line| {
+            let tokens: Vec<_> = line.split('"').collect();
+            match tokens[..] {
+                [before, "path", between, path, after]
+                    if [before, between, after].iter().all(|s| s.trim().is_empty()) =>
+                {
+                    Some(PathBuf::from(path.replace("\\\\", "\\")))
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+fn env_path(name: &str) -> Option<PathBuf> {
+    env::var_os(name)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIBRARY_FOLDERS: &str = r#"
+"libraryfolders"
+{
+	"0"
+	{
+		"path"		"/home/user/.local/share/Steam"
+		"label"		""
+		"contentid"		"4521964128410935121"
+		"apps"
+		{
+			"228980"		"295000520"
+		}
+	}
+	"1"
+	{
+		"path"		"/mnt/games/SteamLibrary"
+		"apps"
+		{
+			"2179850"		"5310941468"
+		}
+	}
+	"2"
+	{
+		"path"		"D:\\Games\\Steam Library"
+	}
+}
+"#;
+
+    #[test]
+    fn library_folders_are_listed_in_order() {
+        assert_eq!(
+            parse_library_folders(LIBRARY_FOLDERS),
+            [
+                PathBuf::from("/home/user/.local/share/Steam"),
+                PathBuf::from("/mnt/games/SteamLibrary"),
+                PathBuf::from(r"D:\Games\Steam Library"),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_library_folders_are_skipped() {
+        let vdf = r#"
+"libraryfolders"
+{
+	"path"
+	"path"		"/unterminated
+	"path"		"/trailing"		"value"
+	path		"/unquoted"
+	"paths"		"/other/key"
+	"path"		"/kept"
+"#;
+        assert_eq!(parse_library_folders(vdf), [PathBuf::from("/kept")]);
+        assert!(parse_library_folders("").is_empty());
+    }
+
+    #[test]
+    fn config_game_path_is_read() {
+        let config = "
+# the install directory of the game
+scale = 2
+
+  game_path =  \"/opt/Ankama/Wakfu\"\t
+";
+        assert_eq!(
+            parse_config_game_path(config),
+            Some(PathBuf::from("/opt/Ankama/Wakfu"))
+        );
+        assert_eq!(
+            parse_config_game_path("game_path=/games/Wakfu"),
+            Some(PathBuf::from("/games/Wakfu"))
+        );
+    }
+
+    #[test]
+    fn config_without_game_path_is_ignored() {
+        assert_eq!(parse_config_game_path(""), None);
+        assert_eq!(parse_config_game_path("# game_path = /commented/out"), None);
+        assert_eq!(parse_config_game_path("game_path /missing/equals"), None);
+        assert_eq!(parse_config_game_path("game_paths = /other/key"), None);
+    }
+}
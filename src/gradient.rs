@@ -0,0 +1,110 @@
+use bevy::asset::{RenderAssetUsages, embedded_asset};
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, MeshVertexBufferLayoutRef, PrimitiveTopology};
+use bevy::render::render_resource::{
+    AsBindGroup, BlendState, RenderPipelineDescriptor, ShaderRef, SpecializedMeshPipelineError,
+};
+use bevy::sprite::{AlphaMode2d, Material2d, Material2dKey, Material2dPlugin};
+
+use crate::scene::Tint;
+
+/// Draws sprites with color gradients as meshes with per-vertex colors.
+pub struct GradientPlugin;
+
+impl Plugin for GradientPlugin {
+    fn build(&self, app: &mut App) {
+        embedded_asset!(app, "gradient.wgsl");
+        app.add_plugins(Material2dPlugin::<GradientMaterial>::default());
+    }
+}
+
+/// A textured material multiplied by the vertex colors of the mesh.
+#[derive(Debug, Clone, Asset, TypePath, AsBindGroup)]
+pub struct GradientMaterial {
+    #[texture(0)]
+    #[sampler(1)]
+    pub texture: Handle<Image>,
+}
+
+impl Material2d for GradientMaterial {
+    fn fragment_shader() -> ShaderRef {
+        "embedded://vakfu/gradient.wgsl".into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode2d {
+        AlphaMode2d::Blend
+    }
+
+    fn specialize(
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayoutRef,
+        _key: Material2dKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        // the textures use premultiplied alpha, same as the regular sprites
+        if let Some(fragment) = &mut descriptor.fragment {
+            for target in fragment.targets.iter_mut().flatten() {
+                target.blend = Some(BlendState::PREMULTIPLIED_ALPHA_BLENDING);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A sprite drawn as a mesh with a [`GradientMaterial`].
+#[derive(Debug, Component)]
+pub struct GradientSprite {
+    /// The rectangles of the animation frames within the texture layout.
+    pub frames: Vec<URect>,
+    /// The size of the texture layout.
+    pub texture_size: UVec2,
+    /// Whether the texture is horizontally flipped.
+    pub flip_x: bool,
+    /// The color the vertex colors were computed from.
+    pub tint: Tint,
+}
+
+impl GradientSprite {
+    /// Builds the mesh of the sprite, anchored at its top-left corner.
+    pub fn mesh(&self, size: Vec2, tint: &Tint) -> Mesh {
+        Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::default(),
+        )
+        .with_inserted_attribute(
+            Mesh::ATTRIBUTE_POSITION,
+            vec![
+                [0., 0., 0.],
+                [size.x, 0., 0.],
+                [size.x, -size.y, 0.],
+                [0., -size.y, 0.],
+            ],
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs(0))
+        .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, vertex_colors(tint))
+        .with_inserted_indices(Indices::U32(vec![0, 3, 2, 0, 2, 1]))
+    }
+
+    /// Returns the texture coordinates of the mesh vertices for the given animation frame.
+    pub fn uvs(&self, frame: usize) -> Vec<[f32; 2]> {
+        let Some(rect) = self.frames.get(frame) else {
+            return vec![[0., 0.]; 4];
+        };
+        let size = self.texture_size.as_vec2();
+        let min = rect.min.as_vec2() / size;
+        let max = rect.max.as_vec2() / size;
+        let (left, right) = if self.flip_x {
+            (max.x, min.x)
+        } else {
+            (min.x, max.x)
+        };
+        vec![[left, min.y], [right, min.y], [right, max.y], [left, max.y]]
+    }
+}
+
+/// Returns the colors of the mesh vertices, with the top and bottom edges taking
+/// the respective gradient colors.
+pub fn vertex_colors(tint: &Tint) -> Vec<[f32; 4]> {
+    let top = tint.top.to_f32_array();
+    let bottom = tint.bottom.to_f32_array();
+    vec![top, top, bottom, bottom]
+}
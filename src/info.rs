@@ -0,0 +1,148 @@
+use std::collections::BTreeSet;
+
+use vakfu::assets::{Map, MapSpriteLibrary};
+use vakfu::game::GameMaps;
+
+/// A summary of the content of a map.
+struct MapSummary {
+    chunks: usize,
+    /// The minimum and maximum `(x, y, z)` cell coordinates of all chunks.
+    bounds: Option<((i32, i32, i16), (i32, i32, i16))>,
+    elements: usize,
+    groups: usize,
+    layers: BTreeSet<u8>,
+    has_light_map: bool,
+    textures: BTreeSet<i32>,
+    /// The number of elements whose definition is missing from the library.
+    unknown_elements: usize,
+    /// The number of elements whose group index is out of the range of their chunk's groups.
+    bad_groups: usize,
+}
+
+impl MapSummary {
+    fn new(map: &Map, sprites: &MapSpriteLibrary, has_light_map: bool) -> Self {
+        let bounds = map
+            .chunks()
+            .iter()
+            .map(|chunk| (chunk.min(), chunk.max()))
+            .reduce(
+                |((x0, y0, z0), (x1, y1, z1)), ((a0, b0, c0), (a1, b1, c1))| {
+                    (
+                        (x0.min(a0), y0.min(b0), z0.min(c0)),
+                        (x1.max(a1), y1.max(b1), z1.max(c1)),
+                    )
+                },
+            );
+
+        let mut groups = BTreeSet::new();
+        let mut layers = BTreeSet::new();
+        let mut textures = BTreeSet::new();
+        let mut elements = 0;
+        let mut unknown_elements = 0;
+        let mut bad_groups = 0;
+        for elem in map.chunks().iter().flat_map(|chunk| chunk.elements()) {
+            elements += 1;
+            match elem.try_group() {
+                Some(group) => {
+                    groups.insert(group.id());
+                    layers.insert(group.layer());
+                }
+                None => bad_groups += 1,
+            }
+            match sprites.get(elem.definition_id()) {
+                Some(def) => {
+                    textures.insert(def.texture_id());
+                }
+                None => unknown_elements += 1,
+            }
+        }
+
+        Self {
+            chunks: map.chunks().len(),
+            bounds,
+            elements,
+            groups: groups.len(),
+            layers,
+            has_light_map,
+            textures,
+            unknown_elements,
+            bad_groups,
+        }
+    }
+
+    fn load(maps: &GameMaps, sprites: &MapSpriteLibrary, map_id: i32) -> anyhow::Result<Self> {
+        let map = maps.load_map(map_id)?;
+        let has_light_map = maps.light_path(map_id).exists();
+        Ok(Self::new(&map, sprites, has_light_map))
+    }
+
+    fn bounds_label(&self) -> String {
+        match self.bounds {
+            Some(((min_x, min_y, min_z), (max_x, max_y, max_z))) => {
+                format!("({min_x}, {min_y}, {min_z})..({max_x}, {max_y}, {max_z})")
+            }
+            None => "empty".to_owned(),
+        }
+    }
+}
+
+/// Prints a detailed summary of a map, including the textures it references.
+pub fn print_map_info(maps: &GameMaps, map_id: i32) -> anyhow::Result<()> {
+    let sprites = maps.load_library()?;
+    let summary = MapSummary::load(maps, &sprites, map_id)?;
+    let layers: Vec<_> = summary.layers.iter().map(u8::to_string).collect();
+    let textures: Vec<_> = summary.textures.iter().map(i32::to_string).collect();
+
+    println!("map:              {map_id}");
+    println!("chunks:           {}", summary.chunks);
+    println!("cell bounds:      {}", summary.bounds_label());
+    println!("elements:         {}", summary.elements);
+    println!("unknown elements: {}", summary.unknown_elements);
+    println!("bad group refs:   {}", summary.bad_groups);
+    println!("groups:           {}", summary.groups);
+    println!("layers:           {}", layers.join(", "));
+    println!(
+        "light map:        {}",
+        if summary.has_light_map { "yes" } else { "no" }
+    );
+    println!("textures ({}):", textures.len());
+    for line in textures.chunks(12) {
+        println!("  {}", line.join(", "));
+    }
+    Ok(())
+}
+
+/// Prints the IDs of the maps, each followed by a summary of its content with `details`.
+///
+/// Maps that fail to load are reported in place of their summary.
+pub fn list_maps(maps: &GameMaps, details: bool) -> anyhow::Result<()> {
+    let map_ids = maps.map_ids()?;
+    if !details {
+        for map_id in map_ids {
+            println!("{map_id}");
+        }
+        return Ok(());
+    }
+
+    let sprites = maps.load_library()?;
+    println!(
+        "{:>6} {:>6} {:>8} {:>6} {:>6} {:>5} {:>8}  bounds",
+        "map", "chunks", "elements", "groups", "layers", "light", "textures"
+    );
+    for map_id in map_ids {
+        match MapSummary::load(maps, &sprites, map_id) {
+            Ok(summary) => println!(
+                "{map_id:>6} {:>6} {:>8} {:>6} {:>6} {:>5} {:>8}  {}",
+                summary.chunks,
+                summary.elements,
+                summary.groups,
+                summary.layers.len(),
+                if summary.has_light_map { "yes" } else { "no" },
+                summary.textures.len(),
+                summary.bounds_label()
+            ),
+            Err(err) => println!("{map_id:>6} could not be loaded: {err:#}"),
+        }
+    }
+    Ok(())
+}
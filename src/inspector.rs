@@ -0,0 +1,177 @@
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use bevy_egui::{EguiContexts, egui};
+use byte::TryRead;
+use byte::ctx::LittleEndian;
+use hashbrown::HashMap;
+
+use crate::assets::{Animation, JarAssetSource, Tgam};
+use crate::render::{AnimationState, MapRenderer};
+use crate::scene::SceneElement;
+use crate::settings::MapViewSettings;
+
+/// The maximum distance in pixels the cursor can travel between a press and a release
+/// for it to count as a click rather than a camera drag.
+const CLICK_TOLERANCE: f32 = 4.;
+
+#[derive(Resource)]
+pub struct Inspector {
+    textures: JarAssetSource,
+    texture_cache: HashMap<i32, Option<Vec<u8>>>,
+    press_position: Option<Vec2>,
+    selected: Option<Selection>,
+}
+
+struct Selection {
+    element: SceneElement,
+    entity: Option<Entity>,
+}
+
+impl Inspector {
+    pub fn new(textures: JarAssetSource) -> Self {
+        Self {
+            textures,
+            texture_cache: HashMap::new(),
+            press_position: None,
+            selected: None,
+        }
+    }
+
+    /// Deselects the inspected element.
+    pub fn clear_selection(&mut self) {
+        self.selected = None;
+    }
+
+    fn hit_test(&mut self, element: &SceneElement, frame: usize, point: Vec2) -> bool {
+        let textures = &self.textures;
+        let bytes = self
+            .texture_cache
+            .entry(element.texture_id)
+            .or_insert_with(|| {
+                textures
+                    .read_entry(&format!("gfx/{}.tgam", element.texture_id))
+                    .ok()
+            });
+        let tgam = bytes
+            .as_deref()
+            .map(|bytes| Tgam::try_read(bytes, LittleEndian));
+        match tgam {
+            Some(Ok((tgam, _))) => element.hit_test(&tgam, frame, point.x, point.y),
+            // fall back to the bounds of the sprite when the texture is not available
+            _ => true,
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn picking_system(
+    mut contexts: EguiContexts<'_, '_>,
+    buttons: Res<'_, ButtonInput<MouseButton>>,
+    window: Query<'_, '_, &Window, With<PrimaryWindow>>,
+    cameras: Query<'_, '_, (&Camera, &GlobalTransform)>,
+    animations: Query<'_, '_, &AnimationState>,
+    renderer: Res<'_, MapRenderer>,
+    settings: Res<'_, MapViewSettings>,
+    mut inspector: ResMut<'_, Inspector>,
+) -> Result {
+    let window = window.single()?;
+    let Some(cursor) = window.cursor_position() else {
+        return Ok(());
+    };
+
+    if buttons.just_pressed(MouseButton::Left) {
+        let over_ui = contexts
+            .try_ctx_mut()
+            .is_some_and(|ctx| ctx.is_pointer_over_area());
+        inspector.press_position = (!over_ui).then_some(cursor);
+    }
+    if !buttons.just_released(MouseButton::Left) {
+        return Ok(());
+    }
+    let Some(pressed) = inspector.press_position.take() else {
+        return Ok(());
+    };
+    if pressed.distance(cursor) > CLICK_TOLERANCE {
+        return Ok(());
+    }
+
+    let (camera, transform) = cameras.single()?;
+    let point = camera.viewport_to_world_2d(transform, cursor)?;
+
+    let inspector = &mut *inspector;
+    let picked = renderer.pick(point.x, point.y, &settings, |element, entity| {
+        inspector.hit_test(element, current_frame(&animations, entity), point)
+    });
+    inspector.selected = picked.map(|(element, entity)| Selection {
+        element: element.clone(),
+        entity,
+    });
+
+    Ok(())
+}
+
+pub fn inspector_ui_system(
+    mut contexts: EguiContexts<'_, '_>,
+    mut inspector: ResMut<'_, Inspector>,
+    animations: Query<'_, '_, &AnimationState>,
+) {
+    let Some(selection) = &inspector.selected else {
+        return;
+    };
+    let element = &selection.element;
+
+    let mut open = true;
+    egui::Window::new("Inspector")
+        .open(&mut open)
+        .show(contexts.ctx_mut(), |ui| {
+            egui::Grid::new("inspector").num_columns(2).show(ui, |ui| {
+                let (x, y, z) = element.cell;
+                row(ui, "Cell", format!("{x}, {y}, {z}"));
+                row(ui, "Definition ID", element.definition_id);
+                if element.missing_definition {
+                    row(ui, "Definition", "missing, drawn as a placeholder");
+                }
+                row(ui, "Texture ID", element.texture_id);
+                row(ui, "Group key", element.group);
+                row(ui, "Layer", element.layer);
+                row(ui, "Color", format!("{:?}", element.color_entry));
+                row(ui, "Slope", element.flags.slope());
+                row(ui, "Walkable", element.flags.is_walkable());
+                row(ui, "Before mobile", element.flags.is_before_mobile());
+                row(ui, "Move top", element.flags.is_move_top());
+                row(ui, "Flip", element.flags.is_flip());
+
+                match &element.animation {
+                    Animation::None => row(ui, "Animation", "none"),
+                    Animation::Frames(frames) => {
+                        let frame = current_frame(&animations, selection.entity);
+                        let count = frames.iter().len();
+                        row(ui, "Animation", format!("frame {} of {count}", frame + 1));
+                        row(
+                            ui,
+                            "Frame size",
+                            format!("{}x{}", frames.width(), frames.height()),
+                        );
+                        row(ui, "Total time", format!("{} ms", frames.total_time()));
+                    }
+                }
+            });
+        });
+
+    if !open {
+        inspector.selected = None;
+    }
+}
+
+fn row(ui: &mut egui::Ui, label: &str, value: impl ToString) {
+    ui.label(label);
+    ui.label(value.to_string());
+    ui.end_row();
+}
+
+/// Returns the animation frame currently displayed by an entity.
+fn current_frame(animations: &Query<'_, '_, &AnimationState>, entity: Option<Entity>) -> usize {
+    entity
+        .and_then(|entity| animations.get(entity).ok())
+        .map_or(0, AnimationState::frame)
+}
@@ -0,0 +1,18 @@
+//! Parsers for the Wakfu map formats, with an optional bevy-based renderer.
+pub mod assets;
+#[cfg(feature = "bevy")]
+pub mod browser;
+pub mod game;
+#[cfg(feature = "bevy")]
+pub mod gradient;
+#[cfg(feature = "bevy")]
+pub mod inspector;
+pub mod raster;
+#[cfg(feature = "bevy")]
+pub mod render;
+pub mod scene;
+#[cfg(feature = "bevy")]
+pub mod settings;
+#[cfg(feature = "bevy")]
+pub mod streaming;
+mod util;
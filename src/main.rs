@@ -1,68 +1,179 @@
-use std::fs;
 use std::fs::File;
 use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
 use anyhow::bail;
-use assets::{JarAssetSource, LightMap, Map, MapSpriteLibrary, TgamLoader};
 use bevy::asset::io::AssetSourceBuilder;
 use bevy::prelude::*;
 use bevy::window::PrimaryWindow;
 use bevy_egui::{EguiContextPass, EguiPlugin};
 use camera::{CameraController, camera_controller_system, camera_system};
+use cli::UsageError;
+use dump::DumpFormat;
 use pico_args::Arguments;
-use render::{MapRenderer, animation_system, rendering_system};
-use settings::{MapViewSettings, settings_ui_system};
-
-#[allow(unused)]
-mod assets;
+use vakfu::assets::{DaylightGradient, JarAssetSource, OutdoorLight, TgamLoader};
+use vakfu::browser::{MapBrowser, browser_ui_system, map_loading_system};
+use vakfu::game::GameMaps;
+use vakfu::gradient::GradientPlugin;
+use vakfu::inspector::{Inspector, inspector_ui_system, picking_system};
+use vakfu::raster::{self, RasterOptions, TextureArchive};
+use vakfu::render::{MapRenderer, animation_system, rendering_system, texture_failure_system};
+use vakfu::scene;
+use vakfu::settings::{MapViewSettings, daylight_system, settings_ui_system};
+use vakfu::streaming::{ChunkStreamer, chunk_streaming_system};
+
+mod animation;
 mod camera;
-mod render;
-mod settings;
-mod util;
-
-fn main() -> anyhow::Result<()> {
+mod cli;
+mod dump;
+mod elements;
+mod info;
+mod textures;
+mod validate;
+
+fn main() -> ExitCode {
     let mut pargs = Arguments::from_env();
 
-    let game_path: PathBuf = pargs.value_from_str("--path")?;
-    let maps_path = game_path.join("contents").join("maps");
+    match run(&mut pargs) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) if err.is::<UsageError>() || err.is::<pico_args::Error>() => {
+            eprintln!("error: {err:#}");
+            eprintln!("run `vakfu --help` for usage");
+            ExitCode::from(2)
+        }
+        Err(err) => {
+            eprintln!("error: {err:#}");
+            ExitCode::FAILURE
+        }
+    }
+}
 
-    let map_id = match pargs.value_from_str::<&str, i32>("--map") {
-        Ok(id) => id,
-        Err(err) => match get_map_list(&maps_path) {
-            Ok(map_ids) => bail!(
-                "no map specified ({err}), you can try one of the following:\n{}",
-                map_ids.join(", ")
-            ),
-            Err(_) => bail!("no map specified ({err}) and could not find anyh"),
-        },
-    };
+fn run(pargs: &mut Arguments) -> anyhow::Result<()> {
+    let command = pargs.subcommand()?;
+    if pargs.contains(["-h", "--help"]) {
+        match command.as_deref() {
+            None => cli::print_help(),
+            Some(name) => match cli::command(name) {
+                Some(command) => cli::print_command_help(command),
+                None => bail!(UsageError(format!("unknown command '{name}'"))),
+            },
+        }
+        return Ok(());
+    }
+
+    match command.as_deref() {
+        None | Some("view") => {
+            let maps = cli::game_maps(pargs)?;
+            let map_id: Option<i32> = pargs.opt_value_from_str("--map")?;
+            cli::finish(pargs)?;
+            view(maps, map_id)
+        }
+        Some("render") => {
+            let maps = cli::game_maps(pargs)?;
+            let map_id = cli::require_map(&maps, pargs.opt_value_from_str("--map")?)?;
+            let out: PathBuf = pargs.value_from_str("--out")?;
+            let scale: f32 = pargs.opt_value_from_str("--scale")?.unwrap_or(1.);
+            if !scale.is_finite() || scale <= 0. {
+                bail!(UsageError(format!(
+                    "invalid scale '{scale}', expected a number greater than 0"
+                )));
+            }
+            let options = RasterOptions {
+                scale,
+                enable_light: !pargs.contains("--no-light"),
+                outdoor_light: pargs
+                    .opt_value_from_str("--time")?
+                    .map_or(OutdoorLight::DAY, |hours| {
+                        DaylightGradient::default().sample(hours)
+                    }),
+            };
+            cli::finish(pargs)?;
+            render_to_file(&maps, map_id, &out, options)
+        }
+        Some("list-maps") => {
+            let maps = cli::game_maps(pargs)?;
+            let details = pargs.contains("--details");
+            cli::finish(pargs)?;
+            info::list_maps(&maps, details)
+        }
+        Some("info") => {
+            let maps = cli::game_maps(pargs)?;
+            let map_id = cli::require_map(&maps, pargs.opt_value_from_str("--map")?)?;
+            cli::finish(pargs)?;
+            info::print_map_info(&maps, map_id)
+        }
+        Some("dump-map") => {
+            let maps = cli::game_maps(pargs)?;
+            let map_id = cli::require_map(&maps, pargs.opt_value_from_str("--map")?)?;
+            let format = pargs
+                .opt_value_from_str("--format")?
+                .unwrap_or(DumpFormat::Json);
+            let out: Option<PathBuf> = pargs.opt_value_from_str("--out")?;
+            cli::finish(pargs)?;
+            dump::dump_map(&maps, map_id, format, out.as_deref())
+        }
+        Some("validate") => {
+            let maps = cli::game_maps(pargs)?;
+            cli::finish(pargs)?;
+            validate::validate(&maps)
+        }
+        Some("elements") => elements::elements(pargs),
+        Some("tgam2png") => textures::tgam_to_png(pargs),
+        Some("png2tgam") => textures::png_to_tgam(pargs),
+        Some("extract-textures") => textures::extract_textures(pargs),
+        Some("export-animation") => animation::export_animation(pargs),
+        Some(command) => bail!(UsageError(format!("unknown command '{command}'"))),
+    }
+}
 
-    let asset_source = JarAssetSource::new(maps_path.join("gfx.jar"))?;
-    let renderer = load_renderer(&maps_path, map_id)?;
+fn view(maps: GameMaps, map_id: Option<i32>) -> anyhow::Result<()> {
+    let asset_source = JarAssetSource::new(maps.textures_path())?;
+    let mut browser = MapBrowser::new(maps)?;
+    if let Some(map_id) = map_id {
+        browser.request(map_id);
+    }
 
     App::new()
         .register_asset_source(
             "gfx",
-            AssetSourceBuilder::default().with_reader(move || Box::new(asset_source.clone())),
+            AssetSourceBuilder::default().with_reader({
+                let asset_source = asset_source.clone();
+                move || Box::new(asset_source.clone())
+            }),
         )
         .add_plugins(DefaultPlugins)
+        .add_plugins(GradientPlugin)
         .add_plugins(EguiPlugin {
             enable_multipass_for_primary_context: true,
         })
         .init_asset_loader::<TgamLoader>()
-        .insert_resource(renderer)
+        .init_resource::<MapRenderer>()
+        .init_resource::<ChunkStreamer>()
+        .insert_resource(browser)
+        .insert_resource(Inspector::new(asset_source))
         .insert_resource(CameraController::default())
         .init_resource::<MapViewSettings>()
         .add_systems(Startup, setup)
         .add_systems(
             EguiContextPass,
-            settings_ui_system.run_if(egui_has_primary_context),
+            (settings_ui_system, browser_ui_system, inspector_ui_system)
+                .run_if(egui_has_primary_context),
         )
         .add_systems(
             Update,
             (
-                (camera_controller_system, camera_system, rendering_system).chain(),
+                (
+                    map_loading_system,
+                    camera_controller_system,
+                    camera_system,
+                    daylight_system,
+                    chunk_streaming_system,
+                    rendering_system,
+                )
+                    .chain(),
                 animation_system,
+                texture_failure_system,
+                picking_system,
             ),
         )
         .run();
@@ -70,21 +181,29 @@ fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
-fn load_renderer(maps_path: &Path, map_id: i32) -> anyhow::Result<MapRenderer> {
-    let map_path = maps_path.join("gfx").join(format!("{}.jar", map_id));
-    let lib_path = maps_path.join("data.jar");
-    let light_path = maps_path.join("light").join(format!("{}.jar", map_id));
-
-    let map = Map::load(File::open(map_path)?)?;
-    let sprites = MapSpriteLibrary::load(File::open(lib_path)?)?;
-
-    let light_map = if light_path.exists() {
-        LightMap::load(File::open(light_path)?)?
-    } else {
-        LightMap::default()
-    };
-
-    Ok(MapRenderer::new(&map, &sprites, &light_map))
+fn render_to_file(
+    maps: &GameMaps,
+    map_id: i32,
+    out: &Path,
+    options: RasterOptions,
+) -> anyhow::Result<()> {
+    let map = maps.load_map(map_id)?;
+    let sprites = maps.load_library()?;
+    let light_map = maps.load_light_map(map_id)?;
+    let elements = scene::layout(&map, &sprites, &light_map);
+
+    let mut textures = TextureArchive::open(maps.textures_path())?;
+    let image = raster::rasterize(&elements, &mut textures, options)?;
+    image.write_png(File::create(out)?)?;
+
+    println!(
+        "rendered {} elements to {} ({}x{})",
+        elements.len(),
+        out.display(),
+        image.width(),
+        image.height()
+    );
+    Ok(())
 }
 
 fn setup(mut commands: Commands<'_, '_>) {
@@ -96,15 +215,3 @@ fn egui_has_primary_context(
 ) -> bool {
     !query.is_empty()
 }
-
-fn get_map_list(maps_path: &Path) -> Result<Vec<String>> {
-    let mut map_ids: Vec<String> = fs::read_dir(maps_path.join("gfx"))?
-        .filter_map(Result::ok)
-        .map(|entry| entry.path())
-        .filter(|entry| entry.extension() == Some("jar".as_ref()))
-        .filter_map(|entry| Some(entry.file_stem()?.to_str()?.to_owned()))
-        .collect();
-    map_ids.sort();
-
-    Ok(map_ids)
-}
@@ -0,0 +1,678 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, Write};
+use std::path::Path;
+
+use hashbrown::HashMap;
+use hashbrown::hash_map::Entry;
+use thiserror::Error;
+
+use crate::assets::{self, AssetError, OutdoorLight, Tgam, TgamBuf};
+use crate::scene::{SceneElement, Tint};
+
+/// The size in pixels of the squares of the placeholder checkerboard.
+const PLACEHOLDER_SQUARE: i64 = 8;
+/// The two colors of the placeholder checkerboard.
+pub const PLACEHOLDER_COLORS: [[u8; 4]; 2] = [[255, 0, 255, 255], [0, 0, 0, 255]];
+/// The largest number of pixels of a rasterized scene, 1 GiB of RGBA data.
+pub const MAX_CANVAS_PIXELS: u64 = 1 << 28;
+
+/// An error that occurred while rasterizing a scene.
+#[derive(Debug, Error)]
+pub enum RasterError {
+    #[error(transparent)]
+    Asset(#[from] AssetError),
+    #[error("invalid scale {0}, expected a finite number greater than 0")]
+    InvalidScale(f32),
+    #[error("the scene has nothing to render")]
+    EmptyScene,
+    #[error(
+        "the image would be {width}x{height} pixels, more than the {MAX_CANVAS_PIXELS} allowed"
+    )]
+    CanvasTooLarge { width: f64, height: f64 },
+}
+
+/// An owned 8-bit RGBA image.
+#[derive(Debug, Clone)]
+pub struct RgbaImage {
+    /// The width of the image in pixels.
+    width: u32,
+    /// The height of the image in pixels.
+    height: u32,
+    /// The pixel data, four bytes per pixel in row-major order.
+    data: Vec<u8>,
+}
+
+impl RgbaImage {
+    /// Creates a fully transparent image.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            data: vec![0; width as usize * height as usize * 4],
+        }
+    }
+
+    /// Creates an image from raw RGBA pixel data.
+    pub fn from_raw(width: u32, height: u32, data: Vec<u8>) -> Option<Self> {
+        (data.len() == width as usize * height as usize * 4).then_some(Self {
+            width,
+            height,
+            data,
+        })
+    }
+
+    /// Returns the width of the image.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the height of the image.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the raw RGBA pixel data.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the pixel at the given coordinates.
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = (y as usize * self.width as usize + x as usize) * 4;
+        [
+            self.data[i],
+            self.data[i + 1],
+            self.data[i + 2],
+            self.data[i + 3],
+        ]
+    }
+
+    /// Copies the area of the image at `(x, y)` with the given size, filling the parts that
+    /// lie outside of the image with transparent pixels.
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> Self {
+        let mut image = Self::new(width, height);
+        image.copy_from(self, x, y, 0, 0, width, height);
+        image
+    }
+
+    /// Draws another image at `(x, y)`, replacing the pixels underneath.
+    pub fn blit(&mut self, image: &Self, x: u32, y: u32) {
+        self.copy_from(image, 0, 0, x, y, image.width, image.height);
+    }
+
+    /// Mirrors the image horizontally.
+    pub fn flip_x(&mut self) {
+        for row in self.data.chunks_exact_mut(self.width as usize * 4) {
+            let pixels = row.len() / 4;
+            for i in 0..pixels / 2 {
+                let j = pixels - 1 - i;
+                for c in 0..4 {
+                    row.swap(i * 4 + c, j * 4 + c);
+                }
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn copy_from(
+        &mut self,
+        src: &Self,
+        src_x: u32,
+        src_y: u32,
+        dest_x: u32,
+        dest_y: u32,
+        width: u32,
+        height: u32,
+    ) {
+        let width = width
+            .min(src.width.saturating_sub(src_x))
+            .min(self.width.saturating_sub(dest_x)) as usize;
+        let height = height
+            .min(src.height.saturating_sub(src_y))
+            .min(self.height.saturating_sub(dest_y));
+        for row in 0..height {
+            let src_i = ((src_y + row) as usize * src.width as usize + src_x as usize) * 4;
+            let dest_i = ((dest_y + row) as usize * self.width as usize + dest_x as usize) * 4;
+            self.data[dest_i..dest_i + width * 4]
+                .copy_from_slice(&src.data[src_i..src_i + width * 4]);
+        }
+    }
+
+    /// Converts the image from premultiplied to straight alpha.
+    pub fn unpremultiply(&mut self) {
+        for px in self.data.chunks_exact_mut(4) {
+            let a = u32::from(px[3]);
+            if a == 0 {
+                continue;
+            }
+            for c in &mut px[..3] {
+                *c = (u32::from(*c) * 255 / a).min(255) as u8;
+            }
+        }
+    }
+
+    /// Converts the image from straight to premultiplied alpha.
+    pub fn premultiply(&mut self) {
+        for px in self.data.chunks_exact_mut(4) {
+            let a = u32::from(px[3]);
+            for c in &mut px[..3] {
+                *c = ((u32::from(*c) * a + 127) / 255) as u8;
+            }
+        }
+    }
+
+    /// Copies the pixels of a TGAM image, cropping the power of two padding.
+    pub fn from_tgam(tgam: &Tgam<'_>) -> Self {
+        let (width, height) = tgam.physical_size();
+        let (width, height) = (u32::from(width), u32::from(height));
+        let stride = tgam.width() as usize * 4;
+        let row_size = width as usize * 4;
+
+        let mut data = Vec::with_capacity(row_size * height as usize);
+        for row in tgam.rgba().chunks(stride).take(height as usize) {
+            data.extend_from_slice(&row[..row_size.min(row.len())]);
+        }
+        data.resize(row_size * height as usize, 0);
+        Self {
+            width,
+            height,
+            data,
+        }
+    }
+
+    /// Renders the mask of a TGAM image at its own resolution, white where the mask is set and
+    /// black elsewhere. Returns `None` if the image has no mask.
+    pub fn from_tgam_mask(tgam: &Tgam<'_>) -> Option<Self> {
+        if tgam.mask().is_empty() {
+            return None;
+        }
+        let (width, height) = tgam.mask_size();
+        let resize = u32::from(tgam.mask_resize().max(1));
+
+        let mut image = Self::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let value = if tgam.mask_bit(x * resize, y * resize).unwrap_or(false) {
+                    255
+                } else {
+                    0
+                };
+                let i = (y as usize * width as usize + x as usize) * 4;
+                image.data[i..i + 4].copy_from_slice(&[value, value, value, 255]);
+            }
+        }
+        Some(image)
+    }
+
+    /// Creates a TGAM image without a mask from the pixels, padding them to powers of two.
+    /// Returns `None` if the image is too large for the format.
+    pub fn to_tgam(&self) -> Option<TgamBuf> {
+        let width = u16::try_from(self.width).ok()?;
+        let height = u16::try_from(self.height).ok()?;
+        if width == 0 || height == 0 {
+            return TgamBuf::new(width, height, Vec::new());
+        }
+        let stride = self.width.next_power_of_two() as usize * 4;
+        let padded_height = self.height.next_power_of_two() as usize;
+        let row_size = self.width as usize * 4;
+
+        let mut rgba = vec![0; stride * padded_height];
+        for (src, dest) in self.data.chunks(row_size).zip(rgba.chunks_mut(stride)) {
+            dest[..src.len()].copy_from_slice(src);
+        }
+        TgamBuf::new(width, height, rgba)
+    }
+
+    /// Decodes a PNG image, converting it to 8-bit RGBA.
+    pub fn read_png<R: BufRead + Seek>(input: R) -> Result<Self, AssetError> {
+        let mut decoder = png::Decoder::new(input);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info()?;
+        let (color_type, _) = reader.output_color_type();
+        let (width, height) = (reader.info().width, reader.info().height);
+
+        let mut buf = vec![0; width as usize * height as usize * color_type.samples()];
+        reader.next_frame(&mut buf)?;
+
+        let data = match color_type {
+            png::ColorType::Rgba => buf,
+            png::ColorType::Rgb => buf
+                .chunks_exact(3)
+                .flat_map(|px| [px[0], px[1], px[2], 255])
+                .collect(),
+            png::ColorType::GrayscaleAlpha => buf
+                .chunks_exact(2)
+                .flat_map(|px| [px[0], px[0], px[0], px[1]])
+                .collect(),
+            png::ColorType::Grayscale | png::ColorType::Indexed => {
+                buf.iter().flat_map(|&px| [px, px, px, 255]).collect()
+            }
+        };
+        Ok(Self {
+            width,
+            height,
+            data,
+        })
+    }
+
+    /// Encodes the image as a PNG.
+    pub fn write_png<W: Write>(&self, output: W) -> Result<(), AssetError> {
+        let mut encoder = png::Encoder::new(BufWriter::new(output), self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.data)?;
+        writer.finish()?;
+        Ok(())
+    }
+}
+
+/// Encodes a looping animated PNG from frames of the same size, each paired with its
+/// duration in milliseconds.
+pub fn write_apng<W: Write>(frames: &[(RgbaImage, u16)], output: W) -> Result<(), AssetError> {
+    let Some((first, _)) = frames.first() else {
+        let err = io::Error::new(io::ErrorKind::InvalidInput, "an animation needs a frame");
+        return Err(err.into());
+    };
+    let mut encoder = png::Encoder::new(BufWriter::new(output), first.width, first.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(frames.len() as u32, 0)?;
+    let mut writer = encoder.write_header()?;
+    for (frame, duration) in frames {
+        writer.set_frame_delay(*duration, 1000)?;
+        writer.write_image_data(&frame.data)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+/// Lazily decodes textures from a `gfx.jar` archive, caching them by texture ID.
+pub struct TextureArchive<R> {
+    archive: zip::ZipArchive<R>,
+    cache: HashMap<i32, Option<RgbaImage>>,
+}
+
+impl TextureArchive<BufReader<File>> {
+    /// Opens a texture archive on disk.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, AssetError> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read + Seek> TextureArchive<R> {
+    /// Creates a texture archive from a zip archive containing `gfx/<id>.tgam` entries.
+    pub fn new(input: R) -> Result<Self, AssetError> {
+        Ok(Self {
+            archive: zip::ZipArchive::new(input)?,
+            cache: HashMap::new(),
+        })
+    }
+
+    /// Returns the decoded texture with the given ID, or `None` if it's not in the archive.
+    pub fn get(&mut self, texture_id: i32) -> Result<Option<&RgbaImage>, AssetError> {
+        let texture = match self.cache.entry(texture_id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(load_texture(&mut self.archive, texture_id)?),
+        };
+        Ok(texture.as_ref())
+    }
+}
+
+fn load_texture<R: Read + Seek>(
+    archive: &mut zip::ZipArchive<R>,
+    texture_id: i32,
+) -> Result<Option<RgbaImage>, AssetError> {
+    let mut entry = match archive.by_name(&format!("gfx/{texture_id}.tgam")) {
+        Ok(entry) => entry,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let mut bytes = Vec::with_capacity(entry.size() as usize);
+    entry.read_to_end(&mut bytes)?;
+
+    let (tgam, _) = assets::decode::<Tgam<'_>>(&bytes, entry.name())?;
+    Ok(RgbaImage::from_raw(
+        tgam.width(),
+        tgam.height(),
+        tgam.rgba().to_vec(),
+    ))
+}
+
+/// Options for rasterizing a scene.
+#[derive(Debug, Clone, Copy)]
+pub struct RasterOptions {
+    /// The number of output pixels per world unit.
+    pub scale: f32,
+    /// Whether to apply the lighting of the map.
+    pub enable_light: bool,
+    /// The outdoor lighting conditions.
+    pub outdoor_light: OutdoorLight,
+}
+
+impl Default for RasterOptions {
+    fn default() -> Self {
+        Self {
+            scale: 1.,
+            enable_light: true,
+            outdoor_light: OutdoorLight::DAY,
+        }
+    }
+}
+
+/// Composites the elements of a scene on the CPU.
+///
+/// The elements are expected to be in drawing order. Textures are treated as premultiplied
+/// and blended the same way the GPU renderer does, with the element color multiplied into
+/// every channel and interpolated vertically for gradients. Animated elements are drawn using
+/// their first frame. The returned image uses straight alpha.
+///
+/// Elements with a missing definition or texture are drawn as a checkerboard. Fails when the
+/// scale isn't positive, when the scene covers no pixels or when the image would have more
+/// than [`MAX_CANVAS_PIXELS`] pixels.
+pub fn rasterize<R: Read + Seek>(
+    elements: &[SceneElement],
+    textures: &mut TextureArchive<R>,
+    options: RasterOptions,
+) -> Result<RgbaImage, RasterError> {
+    let scale = options.scale;
+    if !scale.is_finite() || scale <= 0. {
+        return Err(RasterError::InvalidScale(scale));
+    }
+    let (min_x, max_y, max_x, min_y) = bounds(elements).ok_or(RasterError::EmptyScene)?;
+    let (width, height) = canvas_size(max_x - min_x, max_y - min_y, scale)?;
+    let mut canvas = RgbaImage::new(width, height);
+
+    for elem in elements {
+        let texture = if elem.missing_definition {
+            None
+        } else {
+            textures.get(elem.texture_id)?
+        };
+        let color = if options.enable_light {
+            elem.lit_color(&options.outdoor_light)
+        } else {
+            elem.color
+        };
+        let dest_x = ((elem.x - min_x) * scale).round() as i64;
+        let dest_y = ((max_y - elem.y) * scale).round() as i64;
+        let dest_width = (f32::from(elem.render_size.0) * scale).round() as i64;
+        let dest_height = (f32::from(elem.render_size.1) * scale).round() as i64;
+
+        let dest = [dest_x, dest_y, dest_width, dest_height];
+
+        match texture {
+            Some(texture) => draw(
+                &mut canvas,
+                texture,
+                elem.source_rect(0),
+                (elem.texture_size.0.into(), elem.texture_size.1.into()),
+                dest,
+                color,
+                elem.flip_x,
+            ),
+            None => draw_placeholder(&mut canvas, dest),
+        }
+    }
+
+    canvas.unpremultiply();
+    Ok(canvas)
+}
+
+/// Returns the size in pixels of a canvas covering the given world size, checked against
+/// [`MAX_CANVAS_PIXELS`].
+fn canvas_size(width: f32, height: f32, scale: f32) -> Result<(u32, u32), RasterError> {
+    let width = (f64::from(width) * f64::from(scale)).ceil();
+    let height = (f64::from(height) * f64::from(scale)).ceil();
+    if width < 1. || height < 1. {
+        return Err(RasterError::EmptyScene);
+    }
+    if width * height > MAX_CANVAS_PIXELS as f64 {
+        return Err(RasterError::CanvasTooLarge { width, height });
+    }
+    Ok((width as u32, height as u32))
+}
+
+/// Returns the `(min_x, max_y, max_x, min_y)` world bounds of the elements.
+fn bounds(elements: &[SceneElement]) -> Option<(f32, f32, f32, f32)> {
+    elements
+        .iter()
+        .map(|elem| {
+            (
+                elem.x,
+                elem.y,
+                elem.x + f32::from(elem.render_size.0),
+                elem.y - f32::from(elem.render_size.1),
+            )
+        })
+        .reduce(|(a0, a1, a2, a3), (b0, b1, b2, b3)| {
+            (a0.min(b0), a1.max(b1), a2.max(b2), a3.min(b3))
+        })
+}
+
+/// Fills the destination rectangle with an opaque magenta and black checkerboard.
+fn draw_placeholder(canvas: &mut RgbaImage, [dest_x, dest_y, dest_width, dest_height]: [i64; 4]) {
+    let x_range = dest_x.max(0)..(dest_x + dest_width).min(canvas.width().into());
+    let y_range = dest_y.max(0)..(dest_y + dest_height).min(canvas.height().into());
+
+    for y in y_range {
+        for x in x_range.clone() {
+            let square = (x - dest_x) / PLACEHOLDER_SQUARE + (y - dest_y) / PLACEHOLDER_SQUARE;
+            let pixel = if square % 2 == 0 {
+                PLACEHOLDER_COLORS[0]
+            } else {
+                PLACEHOLDER_COLORS[1]
+            };
+            let i = (y as usize * canvas.width as usize + x as usize) * 4;
+            canvas.data[i..i + 4].copy_from_slice(&pixel);
+        }
+    }
+}
+
+fn draw(
+    canvas: &mut RgbaImage,
+    texture: &RgbaImage,
+    [src_min_x, src_min_y, src_max_x, src_max_y]: [u32; 4],
+    (layout_width, layout_height): (u32, u32),
+    [dest_x, dest_y, dest_width, dest_height]: [i64; 4],
+    color: Tint,
+    flip_x: bool,
+) {
+    if layout_width == 0 || layout_height == 0 || dest_width <= 0 || dest_height <= 0 {
+        return;
+    }
+    // the source rectangle is relative to the atlas layout, which may differ from the texture
+    let scale_x = texture.width() as f32 / layout_width as f32;
+    let scale_y = texture.height() as f32 / layout_height as f32;
+    let src_width = (src_max_x - src_min_x) as f32;
+    let src_height = (src_max_y - src_min_y) as f32;
+
+    let x_range = dest_x.max(0)..(dest_x + dest_width).min(canvas.width().into());
+    let y_range = dest_y.max(0)..(dest_y + dest_height).min(canvas.height().into());
+
+    for y in y_range {
+        let v = ((y - dest_y) as f32 + 0.5) / dest_height as f32;
+        let tex_y = ((src_min_y as f32 + v * src_height) * scale_y) as u32;
+        if tex_y >= texture.height() {
+            continue;
+        }
+        let color = color.at(v);
+        for x in x_range.clone() {
+            let mut u = ((x - dest_x) as f32 + 0.5) / dest_width as f32;
+            if flip_x {
+                u = 1. - u;
+            }
+            let tex_x = ((src_min_x as f32 + u * src_width) * scale_x) as u32;
+            if tex_x >= texture.width() {
+                continue;
+            }
+
+            let src = texture.pixel(tex_x, tex_y);
+            let src = [0, 1, 2, 3].map(|i| (f32::from(src[i]) / 255. * color[i]).min(1.));
+            if src[3] <= 0. && src[..3].iter().all(|&c| c <= 0.) {
+                continue;
+            }
+
+            let i = (y as usize * canvas.width as usize + x as usize) * 4;
+            let src_alpha = src[3];
+            for (dst, src) in canvas.data[i..i + 4].iter_mut().zip(src) {
+                let blended = src + f32::from(*dst) / 255. * (1. - src_alpha);
+                *dst = (blended.clamp(0., 1.) * 255.).round() as u8;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::sync::Arc;
+
+    use zip::write::SimpleFileOptions;
+
+    use super::*;
+    use crate::assets::{Animation, Color, LightDef, SpriteFlags};
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const HALF_GREEN: [u8; 4] = [0, 128, 0, 128];
+    const CLEAR: [u8; 4] = [0, 0, 0, 0];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+
+    /// An archive with a 2x2 texture 1 holding red, translucent green, transparent and blue
+    /// pixels in premultiplied alpha, and a 2x2 texture 2 filled with red.
+    fn textures() -> TextureArchive<Cursor<Vec<u8>>> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (texture_id, pixels) in [(1, [RED, HALF_GREEN, CLEAR, BLUE]), (2, [RED; 4])] {
+            let tgam = TgamBuf::new(2, 2, pixels.concat()).unwrap();
+            writer
+                .start_file(
+                    format!("gfx/{texture_id}.tgam"),
+                    SimpleFileOptions::default(),
+                )
+                .unwrap();
+            writer.write_all(&tgam.encode().unwrap()).unwrap();
+        }
+        TextureArchive::new(Cursor::new(writer.finish().unwrap().into_inner())).unwrap()
+    }
+
+    /// A 2x2 element with its top-left corner at `(x, y)` in world space.
+    fn element(texture_id: i32, x: f32, y: f32, flip_x: bool) -> SceneElement {
+        SceneElement {
+            x,
+            y,
+            z_index: 0,
+            depth: 0,
+            texture_size: (2, 2),
+            render_size: (2, 2),
+            color: Color::None.into(),
+            light: Arc::new(LightDef::DEFAULT),
+            texture_id,
+            flip_x,
+            animation: Animation::None,
+            group: 0,
+            layer: 0,
+            cell: (0, 0, 0),
+            definition_id: 1,
+            color_entry: Color::None,
+            flags: SpriteFlags::new(),
+            missing_definition: false,
+        }
+    }
+
+    fn pixels(image: &RgbaImage) -> Vec<[u8; 4]> {
+        image
+            .data()
+            .chunks_exact(4)
+            .map(|px| [px[0], px[1], px[2], px[3]])
+            .collect()
+    }
+
+    fn rasterize_unlit(elements: &[SceneElement]) -> RgbaImage {
+        let options = RasterOptions {
+            enable_light: false,
+            ..RasterOptions::default()
+        };
+        rasterize(elements, &mut textures(), options).unwrap()
+    }
+
+    #[test]
+    fn element_is_drawn_with_straight_alpha() {
+        let image = rasterize_unlit(&[element(1, 0., 0., false)]);
+        assert_eq!((image.width(), image.height()), (2, 2));
+        assert_eq!(pixels(&image), [RED, [0, 255, 0, 128], CLEAR, BLUE]);
+    }
+
+    #[test]
+    fn flipped_element_is_mirrored() {
+        let image = rasterize_unlit(&[element(1, 0., 0., true)]);
+        assert_eq!(pixels(&image), [[0, 255, 0, 128], RED, BLUE, CLEAR]);
+    }
+
+    #[test]
+    fn translucent_pixels_blend_over_the_previous_elements() {
+        let image = rasterize_unlit(&[element(2, 0., 0., false), element(1, 0., 0., false)]);
+        assert_eq!(pixels(&image), [RED, [127, 128, 0, 255], RED, BLUE]);
+    }
+
+    #[test]
+    fn canvas_covers_the_bounds_of_all_elements() {
+        let image = rasterize_unlit(&[element(2, 0., 0., false), element(1, 1., -1., false)]);
+        assert_eq!((image.width(), image.height()), (3, 3));
+        assert_eq!(image.pixel(0, 0), RED);
+        assert_eq!(image.pixel(2, 0), CLEAR);
+        assert_eq!(image.pixel(1, 1), RED);
+        assert_eq!(image.pixel(2, 2), BLUE);
+        assert_eq!(image.pixel(0, 2), CLEAR);
+    }
+
+    #[test]
+    fn scenes_without_pixels_are_rejected() {
+        let options = RasterOptions::default();
+        let result = rasterize(&[], &mut textures(), options);
+        assert!(matches!(result, Err(RasterError::EmptyScene)));
+
+        let mut flat = element(1, 0., 0., false);
+        flat.render_size = (2, 0);
+        let result = rasterize(&[flat], &mut textures(), options);
+        assert!(matches!(result, Err(RasterError::EmptyScene)));
+    }
+
+    #[test]
+    fn invalid_scales_are_rejected() {
+        for scale in [0., -1., f32::NAN, f32::INFINITY] {
+            let options = RasterOptions {
+                scale,
+                ..RasterOptions::default()
+            };
+            let result = rasterize(&[element(1, 0., 0., false)], &mut textures(), options);
+            assert!(
+                matches!(result, Err(RasterError::InvalidScale(_))),
+                "{scale}"
+            );
+        }
+    }
+
+    #[test]
+    fn oversized_canvas_is_rejected_before_allocating() {
+        let options = RasterOptions {
+            scale: 1e6,
+            ..RasterOptions::default()
+        };
+        let result = rasterize(&[element(1, 0., 0., false)], &mut textures(), options);
+        let Err(RasterError::CanvasTooLarge { width, height }) = result else {
+            panic!("the canvas is larger than the limit");
+        };
+        assert_eq!((width, height), (2e6, 2e6));
+
+        // a scale that exactly reaches the limit is fine
+        let width = (MAX_CANVAS_PIXELS as f64).sqrt() as f32;
+        assert_eq!(canvas_size(width, width, 1.).unwrap(), (16_384, 16_384));
+        assert!(canvas_size(width + 1., width, 1.).is_err());
+    }
+
+    #[test]
+    fn missing_texture_is_drawn_as_a_placeholder() {
+        let image = rasterize_unlit(&[element(3, 0., 0., false)]);
+        assert_eq!(pixels(&image), [PLACEHOLDER_COLORS[0]; 4]);
+    }
+}
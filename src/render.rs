@@ -1,94 +1,188 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsStr;
 use std::sync::Arc;
 use std::time::Duration;
 
+use bevy::asset::{AssetLoadFailedEvent, RenderAssetUsages};
+use bevy::ecs::system::SystemParam;
+use bevy::image::ImageSampler;
 use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
 use bevy::sprite::Anchor;
 use bevy::window::PrimaryWindow;
-use hashbrown::HashSet;
-use rstar::{AABB, RTree, RTreeObject};
+use hashbrown::{HashMap, HashSet};
+use rstar::{AABB, Envelope, RTree, RTreeObject, SelectionFunction};
 
-use crate::assets::{Animation, Frames, Map, MapChunk, MapElementDetails, MapSpriteLibrary, Rgba};
+use crate::assets::{Animation, Frames};
+use crate::gradient::{self, GradientMaterial, GradientSprite};
+use crate::raster::PLACEHOLDER_COLORS;
+use crate::scene::{self, SceneElement, Tint};
 use crate::settings::MapViewSettings;
 
-#[derive(Debug, Resource)]
+/// The elements of the loaded map chunks, indexed spatially for drawing and picking.
+#[derive(Debug, Default, Resource)]
 pub struct MapRenderer {
     rtree: RTree<Renderable>,
+    chunks: HashMap<(i32, i32), AABB<(f32, f32)>>,
     last_seen: HashSet<Entity>,
+    problems: MapProblems,
+    placeholder: Option<Handle<Image>>,
 }
 
 impl MapRenderer {
-    pub fn new(map: &Map, sprites: &MapSpriteLibrary, light_map: &crate::assets::LightMap) -> Self {
-        let mut elements = map
-            .chunks()
-            .iter()
-            .flat_map(MapChunk::elements)
-            .collect::<Vec<_>>();
-        elements.sort_by_key(MapElementDetails::hashcode);
+    /// Returns the coordinates of the loaded chunks.
+    pub fn chunks(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        self.chunks.keys().copied()
+    }
 
-        let sprites = elements
-            .iter()
-            .enumerate()
-            .map(|(z_index, elem)| {
-                let def = &sprites[elem.definition_id()];
-
-                let (x, y) = elem.screen_position();
-                let (origin_x, origin_y) = def.origin();
-                let x = x - origin_x as f32;
-                let y = y + origin_y as f32;
-                let z = z_index as f32 / elements.len() as f32;
-
-                let (width, height) = def.size();
-                let (texture_width, texture_height) = def.texture_size();
-
-                let light_def = light_map.get_color(elem.cell_x(), elem.cell_y(), elem.group().layer() as i32);
-                let [r, g, b, a] = Rgba::from(elem.color()).to_f32_array();
-                let color_tinted = Rgba::new(
-                    r * light_def.ambiance_light[0],
-                    g * light_def.ambiance_light[1],
-                    b * light_def.ambiance_light[2],
-                    a,
-                );
+    /// Returns whether the chunk at the given coordinates is loaded.
+    pub fn has_chunk(&self, coords: (i32, i32)) -> bool {
+        self.chunks.contains_key(&coords)
+    }
 
-                Renderable {
-                    position: Vec3::new(x, y, z),
-                    texture_size: UVec2::new(width.into(), height.into()),
-                    render_size: UVec2::new(texture_width.into(), texture_height.into()),
-                    color: elem.color().into(),
-                    color_tinted,
-                    texture_id: def.texture_id(),
-                    flip_x: def.flags().is_flip(),
-                    animation: def.animation(),
-                    group: elem.group().key(),
-                    layer: elem.group().layer(),
-
-                    id: None,
-                }
-            })
-            .collect();
+    /// Adds the elements of a chunk laid out by [`scene::layout_chunk`], replacing the
+    /// chunk if it was already loaded.
+    pub fn insert_chunk(&mut self, coords: (i32, i32), elements: Vec<SceneElement>) {
+        self.remove_renderables(coords);
+        let mut envelope = AABB::new_empty();
+        for element in elements {
+            if element.missing_definition {
+                self.problems
+                    .unknown_definitions
+                    .entry(element.definition_id)
+                    .or_default()
+                    .insert(element.cell);
+            }
+            let renderable = Renderable {
+                position: Vec3::new(element.x, element.y, 0.),
+                element,
+                chunk: coords,
+
+                id: None,
+            };
+            envelope.merge(&renderable.envelope());
+            self.rtree.insert(renderable);
+        }
+        self.chunks.insert(coords, envelope);
+    }
+
+    /// Removes a chunk and despawns the entities its elements are drawn with.
+    pub fn remove_chunk(&mut self, coords: (i32, i32), commands: &mut Commands<'_, '_>) {
+        for elem in self.remove_renderables(coords) {
+            if let Some(id) = elem.id.filter(|id| self.last_seen.remove(id)) {
+                commands.entity(id).despawn();
+            }
+        }
+    }
 
-        Self {
-            rtree: RTree::bulk_load(sprites),
-            last_seen: HashSet::new(),
+    /// Removes all chunks and despawns the entities drawn for the map.
+    pub fn clear(&mut self, commands: &mut Commands<'_, '_>) {
+        for entity in self.last_seen.drain() {
+            commands.entity(entity).despawn();
         }
+        self.chunks.clear();
+        self.rtree = RTree::new();
+        self.problems = MapProblems::default();
+    }
+
+    /// Returns the problems met while drawing the loaded chunks.
+    pub fn problems(&self) -> &MapProblems {
+        &self.problems
+    }
+
+    /// Takes the elements of a chunk out of the spatial index.
+    fn remove_renderables(&mut self, coords: (i32, i32)) -> Vec<Renderable> {
+        let Some(envelope) = self.chunks.remove(&coords) else {
+            return vec![];
+        };
+        let selection = ChunkSelection { coords, envelope };
+        std::iter::from_fn(|| self.rtree.remove_with_selection_function(&selection)).collect()
+    }
+
+    /// Returns the topmost visible element at the world position `(x, y)` for which `hit`
+    /// returns true, along with the entity it's currently drawn with.
+    pub fn pick(
+        &self,
+        x: f32,
+        y: f32,
+        settings: &MapViewSettings,
+        mut hit: impl FnMut(&SceneElement, Option<Entity>) -> bool,
+    ) -> Option<(&SceneElement, Option<Entity>)> {
+        self.rtree
+            .locate_in_envelope_intersecting(&AABB::from_point((x, y)))
+            .filter(|elem| settings.is_visible(&elem.element) && elem.element.contains(x, y))
+            .filter(|elem| hit(&elem.element, elem.id))
+            .max_by_key(|elem| (elem.element.depth, elem.element.z_index))
+            .map(|elem| (&elem.element, elem.id))
+    }
+}
+
+/// The elements of a map that can't be drawn as intended and are drawn as placeholders.
+#[derive(Debug, Default)]
+pub struct MapProblems {
+    unknown_definitions: BTreeMap<i32, BTreeSet<(i32, i32, i16)>>,
+    failed_textures: BTreeMap<i32, String>,
+}
+
+impl MapProblems {
+    /// Returns the number of missing definitions and failed textures.
+    pub fn len(&self) -> usize {
+        self.unknown_definitions.len() + self.failed_textures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the IDs of the definitions missing from the library, along with the
+    /// `(x, y, z)` cells of the elements using them.
+    pub fn unknown_definitions(&self) -> impl Iterator<Item = (i32, &BTreeSet<(i32, i32, i16)>)> {
+        self.unknown_definitions
+            .iter()
+            .map(|(&definition_id, cells)| (definition_id, cells))
+    }
+
+    /// Returns the IDs of the textures that could not be loaded, along with the reason.
+    pub fn failed_textures(&self) -> impl Iterator<Item = (i32, &str)> {
+        self.failed_textures
+            .iter()
+            .map(|(&texture_id, err)| (texture_id, err.as_str()))
+    }
+
+    fn is_texture_failed(&self, texture_id: i32) -> bool {
+        self.failed_textures.contains_key(&texture_id)
     }
 }
 
 #[derive(Debug)]
 struct Renderable {
     position: Vec3,
-    texture_size: UVec2,
-    render_size: UVec2,
-    color: Rgba,
-    color_tinted: Rgba,
-    texture_id: i32,
-    flip_x: bool,
-    animation: Animation,
-    group: i32,
-    layer: u8,
+    element: SceneElement,
+    chunk: (i32, i32),
 
     id: Option<Entity>,
 }
 
+impl Renderable {
+    fn texture_size(&self) -> UVec2 {
+        let (width, height) = self.element.texture_size;
+        UVec2::new(width.into(), height.into())
+    }
+
+    fn render_size(&self) -> UVec2 {
+        let (width, height) = self.element.render_size;
+        UVec2::new(width.into(), height.into())
+    }
+
+    fn active_color(&self, settings: &MapViewSettings) -> Tint {
+        if settings.enable_light {
+            self.element.lit_color(&settings.outdoor_light)
+        } else {
+            self.element.color
+        }
+    }
+}
+
 impl RTreeObject for Renderable {
     type Envelope = AABB<(f32, f32)>;
 
@@ -96,64 +190,136 @@ impl RTreeObject for Renderable {
         AABB::from_corners(
             (self.position.x, self.position.y),
             (
-                self.position.x + self.render_size.x as f32,
-                self.position.y - self.render_size.y as f32,
+                self.position.x + self.render_size().x as f32,
+                self.position.y - self.render_size().y as f32,
             ),
         )
     }
 }
 
+/// Selects the elements of a chunk, only visiting the nodes overlapping its elements.
+struct ChunkSelection {
+    coords: (i32, i32),
+    envelope: AABB<(f32, f32)>,
+}
+
+impl SelectionFunction<Renderable> for &ChunkSelection {
+    fn should_unpack_parent(&self, envelope: &AABB<(f32, f32)>) -> bool {
+        self.envelope.intersects(envelope)
+    }
+
+    fn should_unpack_leaf(&self, leaf: &Renderable) -> bool {
+        leaf.chunk == self.coords
+    }
+}
+
 #[derive(Component)]
 pub struct AnimationState {
     timer: Timer,
     frames: Arc<Frames>,
+    frame: usize,
+}
+
+impl AnimationState {
+    /// Returns the index of the frame currently displayed.
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+}
+
+/// The assets used to spawn the sprites of map elements.
+#[derive(SystemParam)]
+pub struct SpriteAssets<'w> {
+    server: Res<'w, AssetServer>,
+    images: ResMut<'w, Assets<Image>>,
+    atlas_layouts: ResMut<'w, Assets<TextureAtlasLayout>>,
+    meshes: ResMut<'w, Assets<Mesh>>,
+    materials: ResMut<'w, Assets<GradientMaterial>>,
 }
 
 #[allow(clippy::too_many_arguments)]
 pub fn rendering_system(
     mut commands: Commands<'_, '_>,
-    assets: Res<'_, AssetServer>,
+    mut assets: SpriteAssets<'_>,
     window: Query<'_, '_, &Window, With<PrimaryWindow>>,
-    cameras: Query<'_, '_, &mut Transform, (With<Camera>, Changed<Transform>)>,
-    mut atlas_layouts: ResMut<'_, Assets<TextureAtlasLayout>>,
+    cameras: Query<'_, '_, Ref<'_, Transform>, With<Camera>>,
     mut render_state: ResMut<'_, MapRenderer>,
     settings: Res<'_, MapViewSettings>,
     mut sprites: Query<'_, '_, &mut Sprite>,
+    mut gradients: Query<'_, '_, (&mut GradientSprite, &Mesh2d)>,
+    mut transforms: Query<'_, '_, &mut Transform, Without<Camera>>,
 ) -> Result {
     let Ok(camera) = cameras.single() else {
         return Ok(());
     };
+    if !camera.is_changed() && !settings.is_changed() && !render_state.is_changed() {
+        return Ok(());
+    }
 
-    let window = window.single()?;
-    let view_size = window.size() * camera.scale.truncate();
-    let view_pos = camera.translation.truncate() - view_size / 2.;
-    let aabb = AABB::from_corners(
-        (view_pos.x, view_pos.y),
-        (view_pos.x + view_size.x, view_pos.y + view_size.y),
-    );
+    let view = view_bounds(window.single()?, &camera);
+    let aabb = AABB::from_corners((view.min.x, view.min.y), (view.max.x, view.max.y));
 
     let mut seen = HashSet::new();
-    let MapRenderer { rtree, last_seen } = &mut *render_state;
-
-    for elem in rtree.locate_in_envelope_intersecting_mut(&aabb) {
-        if settings.layer_filter_on && settings.layer != elem.layer {
-            continue;
-        }
-        if settings.group_filter_on && settings.group != elem.group / 1000 {
-            continue;
-        }
+    let MapRenderer {
+        rtree,
+        last_seen,
+        problems,
+        placeholder,
+        ..
+    } = render_state.bypass_change_detection();
+
+    let mut visible: Vec<_> = rtree
+        .locate_in_envelope_intersecting_mut(&aabb)
+        .filter(|elem| settings.is_visible(&elem.element))
+        .collect();
+    // only the visible elements are drawn, so ordering them is enough to layer them and keeps
+    // the depths far apart, which ordering every element of the map in an f32 would not
+    visible.sort_by_key(|elem| (elem.element.depth, elem.element.z_index));
+    let count = visible.len();
+
+    for (i, elem) in visible.into_iter().enumerate() {
+        elem.position.z = i as f32 / count as f32;
 
         let entity = match elem.id {
             Some(id) if commands.get_entity(id).is_ok() => {
+                // the drawing order changes as the view moves
+                if let Some(mut transform) = transforms
+                    .get_mut(id)
+                    .ok()
+                    .filter(|transform| transform.translation != elem.position)
+                {
+                    transform.translation = elem.position;
+                }
+                let tint = elem.active_color(&settings);
                 if let Ok(mut sprite) = sprites.get_mut(id) {
-                    let active_color = if settings.enable_light { elem.color_tinted } else { elem.color };
-                    let [r, g, b, a] = active_color.to_f32_array();
+                    let [r, g, b, a] = tint.top.to_f32_array();
                     sprite.color = Color::linear_rgba(r, g, b, a);
                 }
+                if let Some((mut gradient, mesh)) = gradients
+                    .get_mut(id)
+                    .ok()
+                    .filter(|(gradient, _)| gradient.tint != tint)
+                {
+                    if let Some(mesh) = assets.meshes.get_mut(&mesh.0) {
+                        mesh.insert_attribute(
+                            Mesh::ATTRIBUTE_COLOR,
+                            gradient::vertex_colors(&tint),
+                        );
+                    }
+                    gradient.tint = tint;
+                }
                 id
-            },
+            }
+            _ if elem.element.missing_definition
+                || problems.is_texture_failed(elem.element.texture_id) =>
+            {
+                let image = placeholder_image(placeholder, &mut assets.images);
+                let entity = render_placeholder(&mut commands, image, elem);
+                elem.id = Some(entity);
+                entity
+            }
             _ => {
-                let entity = render(&mut commands, &assets, &mut atlas_layouts, elem, settings.enable_light);
+                let entity = render(&mut commands, &mut assets, elem, &settings);
                 elem.id = Some(entity);
                 entity
             }
@@ -170,76 +336,209 @@ pub fn rendering_system(
     Ok(())
 }
 
-pub fn animation_system(
-    time: Res<'_, Time>,
-    mut query: Query<'_, '_, (&mut AnimationState, &mut Sprite)>,
+/// Records the textures that failed to load and draws the sprites using them as placeholders.
+pub fn texture_failure_system(
+    mut events: EventReader<'_, '_, AssetLoadFailedEvent<Image>>,
+    mut renderer: ResMut<'_, MapRenderer>,
+    mut images: ResMut<'_, Assets<Image>>,
+    mut materials: ResMut<'_, Assets<GradientMaterial>>,
+    mut sprites: Query<'_, '_, &mut Sprite>,
+    gradients: Query<'_, '_, &MeshMaterial2d<GradientMaterial>>,
 ) {
-    for (mut state, mut sprite) in &mut query {
-        let Some(atlas) = &mut sprite.texture_atlas else {
+    for event in events.read() {
+        let texture_id = event
+            .path
+            .path()
+            .file_stem()
+            .and_then(OsStr::to_str)
+            .and_then(|stem| stem.parse().ok());
+        let Some(texture_id) = texture_id else {
             continue;
         };
+        warn!("could not load texture {texture_id}: {}", event.error);
+
+        let renderer = renderer.bypass_change_detection();
+        renderer
+            .problems
+            .failed_textures
+            .insert(texture_id, event.error.to_string());
+        let placeholder = placeholder_image(&mut renderer.placeholder, &mut images);
+
+        for mut sprite in &mut sprites {
+            if sprite.image.id() == event.id {
+                sprite.image = placeholder.clone();
+                sprite.texture_atlas = None;
+            }
+        }
+        for material in &gradients {
+            let uses_texture = materials
+                .get(&material.0)
+                .is_some_and(|material| material.texture.id() == event.id);
+            if let Some(material) = materials.get_mut(&material.0).filter(|_| uses_texture) {
+                material.texture = placeholder.clone();
+            }
+        }
+    }
+}
+
+/// Returns the world space area seen by a camera.
+pub(crate) fn view_bounds(window: &Window, camera: &Transform) -> Rect {
+    let view_size = window.size() * camera.scale.truncate();
+    Rect::from_center_size(camera.translation.truncate(), view_size)
+}
 
+pub fn animation_system(
+    time: Res<'_, Time>,
+    mut meshes: ResMut<'_, Assets<Mesh>>,
+    mut query: Query<
+        '_,
+        '_,
+        (
+            &mut AnimationState,
+            Option<&mut Sprite>,
+            Option<(&GradientSprite, &Mesh2d)>,
+        ),
+    >,
+) {
+    for (mut state, sprite, gradient) in &mut query {
         state.timer.tick(time.delta());
 
-        if let Some((i, _)) = state
+        let Some((i, _)) = state
             .frames
             .iter()
             .enumerate()
             .take_while(|(_, f)| u128::from(f.time) < state.timer.elapsed().as_millis())
             .last()
-        {
+        else {
+            continue;
+        };
+        if i == state.frame {
+            continue;
+        }
+        state.frame = i;
+
+        if let Some(atlas) = sprite.and_then(|sprite| sprite.into_inner().texture_atlas.as_mut()) {
             atlas.index = i;
         }
+        let Some((gradient, mesh)) = gradient else {
+            continue;
+        };
+        if let Some(mesh) = meshes.get_mut(&mesh.0) {
+            mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, gradient.uvs(i));
+        }
     }
 }
 
+/// Returns the checkerboard drawn in place of missing sprites, creating it on first use.
+fn placeholder_image(
+    placeholder: &mut Option<Handle<Image>>,
+    images: &mut Assets<Image>,
+) -> Handle<Image> {
+    placeholder
+        .get_or_insert_with(|| {
+            const SIZE: u32 = 8;
+            let data = (0..SIZE * SIZE)
+                .flat_map(|i| PLACEHOLDER_COLORS[((i % SIZE + i / SIZE) % 2) as usize])
+                .collect();
+            let mut image = Image::new(
+                Extent3d {
+                    width: SIZE,
+                    height: SIZE,
+                    depth_or_array_layers: 1,
+                },
+                TextureDimension::D2,
+                data,
+                TextureFormat::Rgba8Unorm,
+                RenderAssetUsages::RENDER_WORLD,
+            );
+            image.sampler = ImageSampler::nearest();
+            images.add(image)
+        })
+        .clone()
+}
+
+fn render_placeholder(
+    commands: &mut Commands<'_, '_>,
+    image: Handle<Image>,
+    renderable: &Renderable,
+) -> Entity {
+    let mut sprite = Sprite::from_image(image);
+    sprite.custom_size = Some(renderable.render_size().as_vec2());
+    sprite.anchor = Anchor::TopLeft;
+    commands
+        .spawn((sprite, Transform::from_translation(renderable.position)))
+        .id()
+}
+
 fn render(
     commands: &mut Commands<'_, '_>,
-    assets: &Res<'_, AssetServer>,
-    atlas_layouts: &mut ResMut<'_, Assets<TextureAtlasLayout>>,
+    assets: &mut SpriteAssets<'_>,
     renderable: &Renderable,
-    enable_light: bool,
+    settings: &MapViewSettings,
 ) -> Entity {
-    let img = assets.load::<Image>(format!("gfx://gfx/{}.tgam", renderable.texture_id));
-
-    let mut layout = TextureAtlasLayout::new_empty(renderable.texture_size);
-    match &renderable.animation {
-        Animation::None => {
-            layout.add_texture(URect {
-                min: UVec2::ZERO,
-                max: renderable.texture_size,
-            });
-        }
-        Animation::Frames(frames) => {
-            for frame in frames.iter() {
+    let img = assets
+        .server
+        .load::<Image>(format!("gfx://gfx/{}.tgam", renderable.element.texture_id));
+
+    let frames: Vec<URect> = match &renderable.element.animation {
+        Animation::None => vec![URect {
+            min: UVec2::ZERO,
+            max: renderable.texture_size(),
+        }],
+        Animation::Frames(frames) => frames
+            .iter()
+            .map(|frame| {
                 let min = UVec2::new(frame.x.into(), frame.y.into());
                 let max = UVec2::new(
                     (frame.x + frames.width()).into(),
                     (frame.y + frames.height()).into(),
                 );
-                layout.add_texture(URect { min, max });
-            }
+                URect { min, max }
+            })
+            .collect(),
+    };
+    let tint = renderable.active_color(settings);
+    let transform = Transform::from_translation(renderable.position);
+
+    let mut entity = if tint.is_gradient() {
+        let gradient = GradientSprite {
+            frames,
+            texture_size: renderable.texture_size(),
+            flip_x: renderable.element.flip_x,
+            tint,
+        };
+        let mesh = gradient.mesh(renderable.render_size().as_vec2(), &tint);
+        let material = GradientMaterial { texture: img };
+        commands.spawn((
+            Mesh2d(assets.meshes.add(mesh)),
+            MeshMaterial2d(assets.materials.add(material)),
+            gradient,
+            transform,
+        ))
+    } else {
+        let mut layout = TextureAtlasLayout::new_empty(renderable.texture_size());
+        for frame in frames {
+            layout.add_texture(frame);
         }
+        let layout = assets.atlas_layouts.add(layout);
+
+        let mut sprite = Sprite::from_atlas_image(img, layout.into());
+        let [r, g, b, a] = tint.top.to_f32_array();
+        sprite.color = Color::linear_rgba(r, g, b, a);
+        sprite.flip_x = renderable.element.flip_x;
+        sprite.custom_size = Some(renderable.render_size().as_vec2());
+        sprite.anchor = Anchor::TopLeft;
+        commands.spawn((sprite, transform))
     };
-    let layout = atlas_layouts.add(layout);
-
-    let mut sprite = Sprite::from_atlas_image(img, layout.into());
-    let active_color = if enable_light { renderable.color_tinted } else { renderable.color };
-    let [r, g, b, a] = active_color.to_f32_array();
-    sprite.color = Color::linear_rgba(r, g, b, a);
-    sprite.flip_x = renderable.flip_x;
-    sprite.custom_size = Some(renderable.render_size.as_vec2());
-    sprite.anchor = Anchor::TopLeft;
-
-    let mut entity = commands.spawn((sprite, Transform::from_translation(renderable.position)));
 
-    if let Animation::Frames(frames) = &renderable.animation {
+    if let Animation::Frames(frames) = &renderable.element.animation {
         entity.insert(AnimationState {
             timer: Timer::new(
                 Duration::from_millis(frames.total_time().into()),
                 TimerMode::Repeating,
             ),
             frames: frames.clone(),
+            frame: 0,
         });
     }
 
@@ -0,0 +1,290 @@
+use std::sync::Arc;
+
+use crate::assets::{
+    Animation, Color, Group, LightDef, LightMap, Map, MapChunk, MapElementDetails,
+    MapSpriteDefinition, MapSpriteLibrary, OutdoorLight, Rgba, SpriteFlags, Tgam,
+};
+
+/// The size of the placeholder drawn for elements with a missing definition, the size of a cell.
+const PLACEHOLDER_SIZE: (u16, u16) = (86, 43);
+/// The origin of the placeholder, centering it horizontally on the cell.
+const PLACEHOLDER_ORIGIN: (i16, i16) = (43, 0);
+
+/// A map element resolved against the sprite library, positioned and sorted for drawing.
+#[derive(Debug, Clone)]
+pub struct SceneElement {
+    /// The X coordinate of the top-left corner of the sprite in world space.
+    pub x: f32,
+    /// The Y coordinate of the top-left corner of the sprite in world space (pointing up).
+    pub y: f32,
+    /// The position of the element in the drawing order, lower indices are drawn first.
+    pub z_index: usize,
+    /// The sort key of the element, elements with a lower depth are drawn first.
+    pub depth: i64,
+    /// The size of the texture the frame coordinates are relative to.
+    pub texture_size: (u16, u16),
+    /// The size of the sprite on screen.
+    pub render_size: (u16, u16),
+    /// The color of the element.
+    pub color: Tint,
+    /// The lighting of the cell layer the element is in.
+    pub light: Arc<LightDef>,
+    /// The ID of the texture to draw.
+    pub texture_id: i32,
+    /// Whether the texture is horizontally flipped.
+    pub flip_x: bool,
+    /// The animation of the sprite, if any.
+    pub animation: Animation,
+    /// The key of the group the element belongs to.
+    pub group: i32,
+    /// The layer index of the group the element belongs to.
+    pub layer: u8,
+    /// The `(x, y, z)` cell coordinates of the element.
+    pub cell: (i32, i32, i16),
+    /// The ID of the sprite definition of the element.
+    pub definition_id: i32,
+    /// The color palette entry of the element.
+    pub color_entry: Color,
+    /// The flags of the sprite definition.
+    pub flags: SpriteFlags,
+    /// Whether the sprite definition is missing from the library, in which case the element
+    /// is drawn as a placeholder.
+    pub missing_definition: bool,
+}
+
+/// The color of an element, interpolated from the top to the bottom edge of its sprite.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tint {
+    /// The color at the top edge of the sprite.
+    pub top: Rgba,
+    /// The color at the bottom edge of the sprite.
+    pub bottom: Rgba,
+}
+
+impl Tint {
+    /// Returns whether the color varies across the sprite.
+    pub fn is_gradient(&self) -> bool {
+        self.top != self.bottom
+    }
+
+    /// Returns the color at the relative vertical position `v`, from 0 at the top to 1 at the
+    /// bottom of the sprite.
+    pub fn at(&self, v: f32) -> [f32; 4] {
+        let top = self.top.to_f32_array();
+        let bottom = self.bottom.to_f32_array();
+        [0, 1, 2, 3].map(|i| top[i] + (bottom[i] - top[i]) * v)
+    }
+
+    fn map(self, f: impl Fn(Rgba) -> Rgba) -> Self {
+        Self {
+            top: f(self.top),
+            bottom: f(self.bottom),
+        }
+    }
+}
+
+impl From<Color> for Tint {
+    fn from(color: Color) -> Self {
+        let (top, bottom) = color.gradient();
+        Self { top, bottom }
+    }
+}
+
+impl SceneElement {
+    /// Returns the `[min_x, min_y, max_x, max_y]` rectangle of the texture shown for
+    /// the given animation frame, relative to `texture_size`.
+    pub fn source_rect(&self, frame: usize) -> [u32; 4] {
+        let frame = match &self.animation {
+            Animation::Frames(frames) => frames.iter().nth(frame).map(|f| (f, frames)),
+            Animation::None => None,
+        };
+        match frame {
+            Some((frame, frames)) => [
+                frame.x.into(),
+                frame.y.into(),
+                u32::from(frame.x) + u32::from(frames.width()),
+                u32::from(frame.y) + u32::from(frames.height()),
+            ],
+            None => [0, 0, self.texture_size.0.into(), self.texture_size.1.into()],
+        }
+    }
+
+    /// Returns the color of the element with the lighting of its cell applied.
+    pub fn lit_color(&self, outdoor: &OutdoorLight) -> Tint {
+        self.color.map(|color| {
+            let [r, g, b, a] = self.light.apply(color.to_f32_array(), outdoor);
+            Rgba::new(r, g, b, a)
+        })
+    }
+
+    /// Returns whether the world space point `(x, y)` lies within the bounds of the sprite.
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        x >= self.x
+            && x < self.x + f32::from(self.render_size.0)
+            && y <= self.y
+            && y > self.y - f32::from(self.render_size.1)
+    }
+
+    /// Returns whether the world space point `(x, y)` lands on an opaque pixel of the sprite
+    /// when it displays the given animation frame of `texture`.
+    pub fn hit_test(&self, texture: &Tgam<'_>, frame: usize, x: f32, y: f32) -> bool {
+        if !self.contains(x, y) || self.texture_size.0 == 0 || self.texture_size.1 == 0 {
+            return false;
+        }
+        let mut u = (x - self.x) / f32::from(self.render_size.0);
+        let v = (self.y - y) / f32::from(self.render_size.1);
+        if self.flip_x {
+            u = 1. - u;
+        }
+
+        let [min_x, min_y, max_x, max_y] = self.source_rect(frame);
+        let layout_x = min_x as f32 + u * (max_x - min_x) as f32;
+        let layout_y = min_y as f32 + v * (max_y - min_y) as f32;
+        // the source rectangle is relative to the atlas layout, which may differ from the texture
+        let tex_x = layout_x * texture.width() as f32 / f32::from(self.texture_size.0);
+        let tex_y = layout_y * texture.height() as f32 / f32::from(self.texture_size.1);
+
+        texture.is_opaque(tex_x as u32, tex_y as u32)
+    }
+}
+
+/// Resolves all elements of a map and returns them in drawing order.
+///
+/// Elements whose definition is missing from the library are laid out as cell-sized
+/// placeholders with [`SceneElement::missing_definition`] set.
+pub fn layout(map: &Map, sprites: &MapSpriteLibrary, light_map: &LightMap) -> Vec<SceneElement> {
+    let elements = map.chunks().iter().flat_map(MapChunk::elements).collect();
+    layout_elements(elements, sprites, light_map)
+}
+
+/// Resolves the elements of a single chunk and returns them in drawing order.
+///
+/// The `z_index` of the elements is relative to the chunk, elements of different chunks can
+/// be ordered by their `depth` since a cell never spans multiple chunks.
+pub fn layout_chunk(
+    chunk: &MapChunk,
+    sprites: &MapSpriteLibrary,
+    light_map: &LightMap,
+) -> Vec<SceneElement> {
+    layout_elements(chunk.elements().collect(), sprites, light_map)
+}
+
+fn layout_elements(
+    mut elements: Vec<MapElementDetails<'_>>,
+    sprites: &MapSpriteLibrary,
+    light_map: &LightMap,
+) -> Vec<SceneElement> {
+    elements.sort_by_key(MapElementDetails::hashcode);
+
+    elements
+        .iter()
+        .enumerate()
+        .map(|(z_index, elem)| {
+            let def = sprites.get(elem.definition_id());
+            let group = elem.try_group();
+            let layer = group.map_or(0, Group::layer);
+            let color = elem.try_color().unwrap_or(Color::None);
+
+            let (x, y) = elem.screen_position();
+            let (origin_x, origin_y) = def.map_or(PLACEHOLDER_ORIGIN, MapSpriteDefinition::origin);
+
+            SceneElement {
+                x: x - origin_x as f32,
+                y: y + origin_y as f32,
+                z_index,
+                depth: elem.hashcode(),
+                texture_size: def.map_or(PLACEHOLDER_SIZE, MapSpriteDefinition::size),
+                render_size: def.map_or(PLACEHOLDER_SIZE, MapSpriteDefinition::texture_size),
+                color: color.into(),
+                light: light_map.get_color(elem.cell_x(), elem.cell_y(), layer as i32),
+                texture_id: def.map_or(-1, MapSpriteDefinition::texture_id),
+                flip_x: def.is_some_and(|def| def.flags().is_flip()),
+                animation: def.map_or(Animation::None, MapSpriteDefinition::animation),
+                group: group.map_or(0, Group::key),
+                layer,
+                cell: (elem.cell_x(), elem.cell_y(), elem.cell_z()),
+                definition_id: elem.definition_id(),
+                color_entry: color,
+                flags: def.map_or_else(SpriteFlags::new, MapSpriteDefinition::flags),
+                missing_definition: def.is_none(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::TgamBuf;
+
+    /// A 4x2 texture whose top-left and bottom-right pixels are opaque.
+    fn texture() -> TgamBuf {
+        let mut rgba = vec![0; 4 * 2 * 4];
+        rgba[3] = 255;
+        rgba[(4 + 3) * 4 + 3] = 255;
+        TgamBuf::new(4, 2, rgba).unwrap()
+    }
+
+    /// An element drawing the texture at twice its size, with its top-left corner at `(100, 50)`.
+    fn element(flip_x: bool) -> SceneElement {
+        SceneElement {
+            x: 100.,
+            y: 50.,
+            z_index: 0,
+            depth: 0,
+            texture_size: (4, 2),
+            render_size: (8, 4),
+            color: Color::None.into(),
+            light: Arc::new(LightDef::DEFAULT),
+            texture_id: 1,
+            flip_x,
+            animation: Animation::None,
+            group: 0,
+            layer: 0,
+            cell: (0, 0, 0),
+            definition_id: 1,
+            color_entry: Color::None,
+            flags: SpriteFlags::new(),
+            missing_definition: false,
+        }
+    }
+
+    #[test]
+    fn hit_test_follows_the_opaque_pixels() {
+        let texture = texture();
+        let elem = element(false);
+        assert!(elem.hit_test(&texture.as_tgam(), 0, 100.5, 49.5));
+        assert!(!elem.hit_test(&texture.as_tgam(), 0, 103., 49.5));
+        assert!(!elem.hit_test(&texture.as_tgam(), 0, 100.5, 47.5));
+    }
+
+    #[test]
+    fn hit_test_stops_at_the_edges() {
+        let texture = texture();
+        let elem = element(false);
+        assert!(elem.hit_test(&texture.as_tgam(), 0, 107.9, 46.1));
+        assert!(!elem.hit_test(&texture.as_tgam(), 0, 108., 46.1));
+        assert!(!elem.hit_test(&texture.as_tgam(), 0, 107.9, 46.));
+        assert!(!elem.hit_test(&texture.as_tgam(), 0, 99.9, 49.5));
+        assert!(!elem.hit_test(&texture.as_tgam(), 0, 100.5, 50.1));
+    }
+
+    #[test]
+    fn hit_test_mirrors_flipped_sprites() {
+        let texture = texture();
+        let elem = element(true);
+        assert!(elem.hit_test(&texture.as_tgam(), 0, 107.5, 49.5));
+        assert!(!elem.hit_test(&texture.as_tgam(), 0, 100.5, 49.5));
+        assert!(elem.hit_test(&texture.as_tgam(), 0, 100.5, 46.5));
+    }
+
+    #[test]
+    fn hit_test_uses_the_mask_over_the_alpha_channel() {
+        // both 2x2 blocks of the mask hold an opaque pixel, so their transparent pixels are hit
+        let texture = texture().with_generated_mask(2);
+        let elem = element(false);
+        assert!(elem.hit_test(&texture.as_tgam(), 0, 102.5, 49.5));
+        assert!(elem.hit_test(&texture.as_tgam(), 0, 104.5, 49.5));
+        assert!(!elem.hit_test(&texture.as_tgam(), 0, 108.5, 49.5));
+    }
+}
@@ -1,7 +1,12 @@
+use bevy::ecs::change_detection::DetectChangesMut;
 use bevy::ecs::resource::Resource;
-use bevy::ecs::system::ResMut;
+use bevy::ecs::system::{Res, ResMut};
+use bevy::time::Time;
 use bevy_egui::{EguiContexts, egui};
 
+use crate::assets::{DaylightGradient, OutdoorLight};
+use crate::scene::SceneElement;
+
 #[derive(Debug, Resource)]
 pub struct MapViewSettings {
     pub layer_filter_on: bool,
@@ -9,6 +14,13 @@ pub struct MapViewSettings {
     pub group_filter_on: bool,
     pub group: i32,
     pub enable_light: bool,
+    pub outdoor_light: OutdoorLight,
+    pub daylight: DaylightGradient,
+    /// The time of day in hours, in the `[0, 24)` range.
+    pub time_of_day: f32,
+    pub time_playing: bool,
+    /// The number of hours that pass every second while the time is playing.
+    pub time_speed: f32,
 }
 
 impl Default for MapViewSettings {
@@ -19,31 +31,111 @@ impl Default for MapViewSettings {
             group_filter_on: false,
             group: 0,
             enable_light: true,
+            outdoor_light: OutdoorLight::DAY,
+            daylight: DaylightGradient::default(),
+            time_of_day: 12.,
+            time_playing: false,
+            time_speed: 1.,
         }
     }
 }
 
+impl MapViewSettings {
+    /// Returns whether the element passes the layer and group filters.
+    pub fn is_visible(&self, element: &SceneElement) -> bool {
+        (!self.layer_filter_on || self.layer == element.layer)
+            && (!self.group_filter_on || self.group == element.group / 1000)
+    }
+}
+
+/// Shows the settings window, only flagging the settings as changed when a widget edits them
+/// so that the map isn't redrawn on every frame.
 pub fn settings_ui_system(
     mut contexts: EguiContexts<'_, '_>,
     mut settings: ResMut<'_, MapViewSettings>,
 ) {
+    let mut changed = false;
+    let edited = settings.bypass_change_detection();
     egui::Window::new("Settings")
         .movable(false)
         .show(contexts.ctx_mut(), |ui| {
             ui.horizontal(|ui| {
-                ui.checkbox(&mut settings.layer_filter_on, "Layer filter");
-                ui.add_enabled(
-                    settings.layer_filter_on,
-                    egui::Slider::new(&mut settings.layer, 0..=127),
-                );
+                changed |= ui
+                    .checkbox(&mut edited.layer_filter_on, "Layer filter")
+                    .changed();
+                changed |= ui
+                    .add_enabled(
+                        edited.layer_filter_on,
+                        egui::Slider::new(&mut edited.layer, 0..=127),
+                    )
+                    .changed();
+            });
+            ui.horizontal(|ui| {
+                changed |= ui
+                    .checkbox(&mut edited.group_filter_on, "Group filter")
+                    .changed();
+                changed |= ui
+                    .add_enabled(
+                        edited.group_filter_on,
+                        egui::Slider::new(&mut edited.group, -1..=1),
+                    )
+                    .changed();
+            });
+            changed |= ui
+                .checkbox(&mut edited.enable_light, "Enable light")
+                .changed();
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.label("Time of day");
+                changed |= ui
+                    .add(
+                        egui::Slider::new(&mut edited.time_of_day, 0.0..=24.0)
+                            .custom_formatter(|hours, _| format_hours(hours as f32)),
+                    )
+                    .changed();
             });
             ui.horizontal(|ui| {
-                ui.checkbox(&mut settings.group_filter_on, "Group filter");
-                ui.add_enabled(
-                    settings.group_filter_on,
-                    egui::Slider::new(&mut settings.group, -1..=1),
-                );
+                changed |= ui.checkbox(&mut edited.time_playing, "Play").changed();
+                changed |= ui
+                    .add_enabled(
+                        edited.time_playing,
+                        egui::Slider::new(&mut edited.time_speed, 0.1..=24.0)
+                            .logarithmic(true)
+                            .suffix(" h/s"),
+                    )
+                    .changed();
+            });
+            egui::CollapsingHeader::new("Daylight gradient").show(ui, |ui| {
+                for key in edited.daylight.keys_mut() {
+                    ui.horizontal(|ui| {
+                        ui.label(format_hours(key.hour));
+                        changed |= ui.color_edit_button_rgb(&mut key.color).changed();
+                        changed |= ui
+                            .add(egui::Slider::new(&mut key.night, 0.0..=1.0).text("night"))
+                            .changed();
+                    });
+                }
             });
-            ui.checkbox(&mut settings.enable_light, "Enable light");
         });
+    if changed {
+        settings.set_changed();
+    }
+}
+
+/// Advances the time of day and updates the outdoor light accordingly.
+pub fn daylight_system(time: Res<'_, Time>, mut settings: ResMut<'_, MapViewSettings>) {
+    if settings.time_playing {
+        let hours = settings.time_of_day + time.delta_secs() * settings.time_speed;
+        settings.time_of_day = hours.rem_euclid(24.);
+    }
+    let outdoor_light = settings.daylight.sample(settings.time_of_day);
+    if settings.outdoor_light != outdoor_light {
+        settings.outdoor_light = outdoor_light;
+    }
+}
+
+fn format_hours(hours: f32) -> String {
+    let minutes = (hours * 60.).round() as u32 % (24 * 60);
+    format!("{:02}:{:02}", minutes / 60, minutes % 60)
 }
@@ -0,0 +1,177 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::{Arc, Mutex};
+
+use bevy::prelude::*;
+use bevy::tasks::futures_lite::future;
+use bevy::tasks::{AsyncComputeTaskPool, Task, block_on};
+use bevy::window::PrimaryWindow;
+use hashbrown::{HashMap, HashSet};
+
+use crate::assets::{
+    AssetError, CHUNK_SIZE, LightMap, MapArchive, MapChunk, MapSpriteLibrary, iso_to_screen,
+};
+use crate::game::GameMaps;
+use crate::render::{MapRenderer, view_bounds};
+use crate::scene::{self, SceneElement};
+
+/// The distance in world units sprites can extend past the cells of their chunk, to account
+/// for elevation and tall sprites.
+const CHUNK_OVERHANG: f32 = 512.;
+
+/// The chunks of a map, indexed and ready to be streamed in.
+pub struct MapSource {
+    archive: Mutex<MapArchive<BufReader<File>>>,
+    chunks: Vec<(i32, i32)>,
+    sprites: Arc<MapSpriteLibrary>,
+    light_map: LightMap,
+}
+
+impl MapSource {
+    /// Indexes the chunks of a map and loads its light map.
+    pub fn open(
+        maps: &GameMaps,
+        map_id: i32,
+        sprites: Arc<MapSpriteLibrary>,
+    ) -> Result<Self, AssetError> {
+        let archive = MapArchive::open(maps.map_path(map_id))?;
+        Ok(Self {
+            chunks: archive.chunk_coords().collect(),
+            archive: Mutex::new(archive),
+            sprites,
+            light_map: maps.load_light_map(map_id)?,
+        })
+    }
+
+    /// Parses a chunk and lays out its elements.
+    ///
+    /// The archive is only locked while the chunk file is read, so that chunks are parsed in
+    /// parallel.
+    fn load_chunk(&self, (x, y): (i32, i32)) -> Result<Vec<SceneElement>, AssetError> {
+        let entry = self.archive.lock().unwrap().read_chunk_entry(x, y)?;
+        let Some((name, bytes)) = entry else {
+            return Ok(vec![]);
+        };
+        let chunk = MapChunk::decode(&bytes, &name)?;
+        Ok(scene::layout_chunk(&chunk, &self.sprites, &self.light_map))
+    }
+}
+
+/// Streams the chunks of a map into the [`MapRenderer`] as the camera moves.
+#[derive(Resource)]
+pub struct ChunkStreamer {
+    source: Option<Arc<MapSource>>,
+    pending: HashMap<(i32, i32), Task<Result<Vec<SceneElement>, AssetError>>>,
+    failed: HashSet<(i32, i32)>,
+    /// The distance in world units around the view within which chunks are loaded.
+    pub load_margin: f32,
+    /// The distance in world units around the view past which chunks are evicted.
+    pub evict_margin: f32,
+}
+
+impl Default for ChunkStreamer {
+    fn default() -> Self {
+        Self {
+            source: None,
+            pending: HashMap::new(),
+            failed: HashSet::new(),
+            load_margin: 512.,
+            evict_margin: 2048.,
+        }
+    }
+}
+
+impl ChunkStreamer {
+    /// Starts streaming a new map, cancelling the chunks still being loaded.
+    pub fn set_source(&mut self, source: MapSource) {
+        self.source = Some(Arc::new(source));
+        self.pending.clear();
+        self.failed.clear();
+    }
+
+    /// Returns the number of chunks being loaded.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+/// Inserts the chunks that finished loading, evicts the chunks far from the view and starts
+/// loading the chunks near it.
+pub fn chunk_streaming_system(
+    mut commands: Commands<'_, '_>,
+    window: Query<'_, '_, &Window, With<PrimaryWindow>>,
+    cameras: Query<'_, '_, &Transform, With<Camera>>,
+    mut streamer: ResMut<'_, ChunkStreamer>,
+    mut renderer: ResMut<'_, MapRenderer>,
+) -> Result {
+    let ChunkStreamer {
+        source,
+        pending,
+        failed,
+        load_margin,
+        evict_margin,
+    } = &mut *streamer;
+    let Some(source) = source else {
+        return Ok(());
+    };
+
+    pending.retain(|&coords, task| {
+        let Some(result) = block_on(future::poll_once(task)) else {
+            return true;
+        };
+        match result {
+            Ok(elements) => renderer.insert_chunk(coords, elements),
+            Err(err) => {
+                warn!("could not load chunk {coords:?}: {err}");
+                failed.insert(coords);
+            }
+        }
+        false
+    });
+
+    let Ok(camera) = cameras.single() else {
+        return Ok(());
+    };
+    let view = view_bounds(window.single()?, camera);
+    let is_near = |coords: (i32, i32), margin: f32| {
+        !chunk_bounds(coords)
+            .intersect(view.inflate(margin))
+            .is_empty()
+    };
+
+    let far: Vec<_> = renderer
+        .chunks()
+        .filter(|&coords| !is_near(coords, *evict_margin))
+        .collect();
+    for coords in far {
+        renderer.remove_chunk(coords, &mut commands);
+    }
+    pending.retain(|&coords, _| is_near(coords, *evict_margin));
+
+    let pool = AsyncComputeTaskPool::get();
+    for &coords in &source.chunks {
+        if pending.contains_key(&coords)
+            || failed.contains(&coords)
+            || renderer.has_chunk(coords)
+            || !is_near(coords, *load_margin)
+        {
+            continue;
+        }
+        let source = source.clone();
+        pending.insert(coords, pool.spawn(async move { source.load_chunk(coords) }));
+    }
+
+    Ok(())
+}
+
+/// Returns the approximate world space area covered by the sprites of a chunk.
+fn chunk_bounds((x, y): (i32, i32)) -> Rect {
+    let (min_x, min_y) = (x * CHUNK_SIZE, y * CHUNK_SIZE);
+    let (max_x, max_y) = (min_x + CHUNK_SIZE, min_y + CHUNK_SIZE);
+
+    let (left, _) = iso_to_screen(min_x, max_y, 0);
+    let (right, _) = iso_to_screen(max_x, min_y, 0);
+    let (_, top) = iso_to_screen(min_x, min_y, 0);
+    let (_, bottom) = iso_to_screen(max_x, max_y, 0);
+    Rect::new(left, bottom, right, top).inflate(CHUNK_OVERHANG)
+}
@@ -0,0 +1,165 @@
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use anyhow::{Context, bail};
+use pico_args::Arguments;
+use vakfu::assets::{self, JarAssetSource, Tgam};
+use vakfu::raster::RgbaImage;
+
+use crate::cli;
+
+/// Converts a TGAM texture, either a file or a texture of the game, to a PNG.
+///
+/// The texture is cropped to its physical size and converted to straight alpha.
+pub fn tgam_to_png(pargs: &mut Arguments) -> anyhow::Result<()> {
+    let (name, bytes) = match pargs.opt_value_from_str::<_, i32>("--texture")? {
+        Some(texture_id) => {
+            let maps = cli::game_maps(pargs)?;
+            let name = format!("gfx/{texture_id}.tgam");
+            let bytes = JarAssetSource::new(maps.textures_path())?
+                .read_entry(&name)
+                .with_context(|| format!("could not read texture {texture_id}"))?;
+            (name, bytes)
+        }
+        None => {
+            let input: PathBuf = pargs.free_from_str()?;
+            let bytes =
+                fs::read(&input).with_context(|| format!("could not read {}", input.display()))?;
+            (input.display().to_string(), bytes)
+        }
+    };
+    let out: PathBuf = pargs.free_from_str()?;
+    cli::finish(pargs)?;
+
+    let (tgam, _) = assets::decode::<Tgam<'_>>(&bytes, &name)
+        .with_context(|| format!("could not decode {name}"))?;
+    let mut image = RgbaImage::from_tgam(&tgam);
+    image.unpremultiply();
+    image.write_png(File::create(&out)?)?;
+
+    println!(
+        "converted a {}x{} texture to {}",
+        image.width(),
+        image.height(),
+        out.display()
+    );
+    Ok(())
+}
+
+/// Converts a PNG to a TGAM texture, optionally with a mask generated from the alpha channel.
+///
+/// The mask is downscaled by the factor given with `--mask-resize`, `--mask` generates it at
+/// full size.
+pub fn png_to_tgam(pargs: &mut Arguments) -> anyhow::Result<()> {
+    let mask_resize: Option<u8> = match pargs.opt_value_from_str("--mask-resize")? {
+        Some(resize) => Some(resize),
+        None => pargs.contains("--mask").then_some(1),
+    };
+    let input: PathBuf = pargs.free_from_str()?;
+    let out: PathBuf = pargs.free_from_str()?;
+    cli::finish(pargs)?;
+
+    let file = File::open(&input).with_context(|| format!("could not read {}", input.display()))?;
+    let mut image = RgbaImage::read_png(BufReader::new(file))?;
+    image.premultiply();
+
+    let Some(mut tgam) = image.to_tgam() else {
+        bail!(
+            "{}x{} is too large for a texture",
+            image.width(),
+            image.height()
+        );
+    };
+    if let Some(resize) = mask_resize {
+        tgam = tgam.with_generated_mask(resize);
+    }
+    fs::write(&out, tgam.encode()?)?;
+
+    println!(
+        "converted a {}x{} image to {}",
+        image.width(),
+        image.height(),
+        out.display()
+    );
+    Ok(())
+}
+
+/// Extracts every texture of the game to a PNG named after its ID, using all available cores.
+///
+/// With `--mask`, the masks of the textures that have one are written next to them as
+/// `<id>.mask.png`.
+pub fn extract_textures(pargs: &mut Arguments) -> anyhow::Result<()> {
+    let maps = cli::game_maps(pargs)?;
+    let out: PathBuf = pargs.value_from_str("--out")?;
+    let with_mask = pargs.contains("--mask");
+    cli::finish(pargs)?;
+
+    let source = JarAssetSource::new(maps.textures_path())?;
+    let names: Vec<&str> = source
+        .entry_names()
+        .filter(|name| name.starts_with("gfx/") && name.ends_with(".tgam"))
+        .collect();
+    fs::create_dir_all(&out)?;
+
+    let next = AtomicUsize::new(0);
+    let failures = Mutex::new(Vec::new());
+    let workers = thread::available_parallelism().map_or(1, usize::from);
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                while let Some(name) = names.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    if let Err(err) = extract_texture(&source, name, &out, with_mask) {
+                        failures.lock().unwrap().push(format!("{name}: {err:#}"));
+                    }
+                }
+            });
+        }
+    });
+
+    let mut failures = failures.into_inner().unwrap();
+    failures.sort();
+    for failure in &failures {
+        eprintln!("{failure}");
+    }
+    println!(
+        "extracted {} textures to {}",
+        names.len() - failures.len(),
+        out.display()
+    );
+    if !failures.is_empty() {
+        bail!("{} textures could not be extracted", failures.len());
+    }
+    Ok(())
+}
+
+fn extract_texture(
+    source: &JarAssetSource,
+    name: &str,
+    out: &Path,
+    with_mask: bool,
+) -> anyhow::Result<()> {
+    let bytes = source.read_entry(name)?;
+    let (tgam, _) = assets::decode::<Tgam<'_>>(&bytes, name)
+        .with_context(|| format!("could not decode {name}"))?;
+    let stem = Path::new(name)
+        .file_stem()
+        .and_then(OsStr::to_str)
+        .unwrap_or(name);
+
+    let mut image = RgbaImage::from_tgam(&tgam);
+    image.unpremultiply();
+    image.write_png(File::create(out.join(format!("{stem}.png")))?)?;
+
+    let mask = with_mask
+        .then(|| RgbaImage::from_tgam_mask(&tgam))
+        .flatten();
+    if let Some(mask) = mask {
+        mask.write_png(File::create(out.join(format!("{stem}.mask.png")))?)?;
+    }
+    Ok(())
+}
@@ -1,6 +1,91 @@
+use std::cell::RefCell;
 use std::marker::PhantomData;
 
-use byte::{BytesExt, TryRead};
+use byte::{BytesExt, TryRead, TryWrite};
+
+thread_local! {
+    /// The values that were being read when decoding failed, innermost first, or `None` when no
+    /// call to [`trace_reads`] is running on this thread.
+    static DECODE_TRACE: RefCell<Option<Vec<TracedRead>>> = const { RefCell::new(None) };
+}
+
+/// A value that was being read when decoding failed.
+#[derive(Debug, Clone, Copy)]
+pub struct TracedRead {
+    pub type_name: &'static str,
+    /// The position of the value in the sequence it belongs to, if any.
+    pub index: Option<usize>,
+    /// The address of the first byte of the value.
+    pub address: usize,
+}
+
+/// Reads a value at the given offset like [`BytesExt::read_with`], recording its type and
+/// position when it fails to decode.
+///
+/// As the error propagates, every traced read it passes through adds itself to the trace of the
+/// enclosing [`trace_reads`] call. Outside of one, nothing is recorded.
+pub fn read_traced<'a, T, C>(
+    bytes: &'a [u8],
+    offset: &mut usize,
+    ctx: C,
+    index: Option<usize>,
+) -> byte::Result<T>
+where
+    T: TryRead<'a, C>,
+{
+    let rest = bytes
+        .get(*offset..)
+        .ok_or(byte::Error::BadOffset(*offset))?;
+    match T::try_read(rest, ctx) {
+        Ok((value, size)) => {
+            *offset += size;
+            Ok(value)
+        }
+        Err(err) => {
+            let read = TracedRead {
+                type_name: short_type_name::<T>(),
+                index,
+                address: rest.as_ptr() as usize,
+            };
+            DECODE_TRACE.with_borrow_mut(|trace| {
+                if let Some(trace) = trace {
+                    trace.push(read);
+                }
+            });
+            Err(err)
+        }
+    }
+}
+
+/// Runs a decoding function, returning its result along with the reads that failed during it,
+/// innermost first.
+///
+/// The trace only lives for the duration of the call, so reads that fail outside of it, or in a
+/// nested call, don't end up in it.
+pub fn trace_reads<R>(decode: impl FnOnce() -> R) -> (R, Vec<TracedRead>) {
+    /// Restores the trace of the enclosing call, even if the decoding function panics.
+    struct Restore(Option<Vec<TracedRead>>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let outer = self.0.take();
+            DECODE_TRACE.with_borrow_mut(|trace| *trace = outer);
+        }
+    }
+
+    let restore = Restore(DECODE_TRACE.replace(Some(Vec::new())));
+    let result = decode();
+    let trace = DECODE_TRACE.replace(None).unwrap_or_default();
+    drop(restore);
+    (result, trace)
+}
+
+/// Returns the name of a type without its module path or generic arguments.
+fn short_type_name<T>() -> &'static str {
+    let name = std::any::type_name::<T>();
+    let name = name.split('<').next().unwrap_or(name);
+    name.rsplit("::").next().unwrap_or(name)
+}
 
 #[derive(Debug, Clone)]
 pub struct WithSizePrefix<C, L> {
@@ -29,14 +114,36 @@ where
     ) -> byte::Result<(Self, usize)> {
         let offset = &mut 0;
         let len: L = bytes.read(offset, ctx)?;
-        let vec = bytes
-            .read_iter(offset, ctx)
-            .take(len.into())
+        let vec = (0..len.into())
+            .map(|index| read_traced(bytes, offset, ctx, Some(index)))
             .collect::<byte::Result<Vec<A>>>()?;
         Ok((vec, *offset))
     }
 }
 
+impl<A, C, L> TryWrite<WithSizePrefix<C, L>> for &[A]
+where
+    for<'b> &'b A: TryWrite<C>,
+    C: Copy,
+    L: TryFrom<usize> + TryWrite<C>,
+{
+    fn try_write(
+        self,
+        bytes: &mut [u8],
+        WithSizePrefix { ctx, .. }: WithSizePrefix<C, L>,
+    ) -> byte::Result<usize> {
+        let offset = &mut 0;
+        let len = L::try_from(self.len()).map_err(|_| byte::Error::BadInput {
+            err: "too many elements for the size prefix",
+        })?;
+        bytes.write(offset, len, ctx)?;
+        for elem in self {
+            bytes.write(offset, elem, ctx)?;
+        }
+        Ok(*offset)
+    }
+}
+
 pub struct WithSize<C>(C, usize);
 
 impl<C> WithSize<C> {
@@ -52,10 +159,170 @@ where
 {
     fn try_read(bytes: &'a [u8], WithSize(ctx, count): WithSize<C>) -> byte::Result<(Self, usize)> {
         let offset = &mut 0;
-        let vec = bytes
-            .read_iter(offset, ctx)
-            .take(count)
+        let vec = (0..count)
+            .map(|index| read_traced(bytes, offset, ctx, Some(index)))
             .collect::<byte::Result<Vec<A>>>()?;
         Ok((vec, *offset))
     }
 }
+
+impl<A, C> TryWrite<WithSize<C>> for &[A]
+where
+    for<'b> &'b A: TryWrite<C>,
+    C: Copy,
+{
+    fn try_write(self, bytes: &mut [u8], WithSize(ctx, count): WithSize<C>) -> byte::Result<usize> {
+        if self.len() != count {
+            return Err(byte::Error::BadInput {
+                err: "element count does not match the expected size",
+            });
+        }
+        let offset = &mut 0;
+        for elem in self {
+            bytes.write(offset, elem, ctx)?;
+        }
+        Ok(*offset)
+    }
+}
+
+/// The size past which [`write_to_vec`] stops growing its buffer, well above the largest
+/// textures of the game.
+const MAX_WRITE_LEN: usize = 1 << 28;
+
+/// Serializes a value into a new buffer, growing it until the value fits.
+///
+/// Writes past the end of the buffer fail with either [`byte::Error::Incomplete`] or
+/// [`byte::Error::BadOffset`], so both grow the buffer up to [`MAX_WRITE_LEN`], after which
+/// the error is returned.
+pub fn write_to_vec<T, C>(value: T, ctx: C) -> byte::Result<Vec<u8>>
+where
+    T: TryWrite<C> + Copy,
+    C: Copy,
+{
+    write_to_vec_within(value, ctx, MAX_WRITE_LEN)
+}
+
+fn write_to_vec_within<T, C>(value: T, ctx: C, max_len: usize) -> byte::Result<Vec<u8>>
+where
+    T: TryWrite<C> + Copy,
+    C: Copy,
+{
+    let mut bytes = vec![0; 4096.min(max_len)];
+    loop {
+        match value.try_write(&mut bytes, ctx) {
+            Ok(len) => {
+                bytes.truncate(len);
+                return Ok(bytes);
+            }
+            Err(byte::Error::Incomplete | byte::Error::BadOffset(_)) if bytes.len() < max_len => {
+                bytes.resize((bytes.len() * 2).min(max_len), 0);
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Copies raw bytes into the buffer at the given offset.
+pub fn write_slice(bytes: &mut [u8], offset: &mut usize, data: &[u8]) -> byte::Result<()> {
+    let dest = bytes
+        .get_mut(*offset..*offset + data.len())
+        .ok_or(byte::Error::Incomplete)?;
+    dest.copy_from_slice(data);
+    *offset += data.len();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use byte::ctx::LittleEndian;
+
+    use super::*;
+
+    fn read_u32(bytes: &[u8], index: usize) -> byte::Result<u32> {
+        read_traced(bytes, &mut 0, LittleEndian, Some(index))
+    }
+
+    #[test]
+    fn failed_reads_are_traced_within_the_call() {
+        let (result, trace) = trace_reads(|| read_u32(&[1, 2], 3));
+        assert!(result.is_err());
+        assert_eq!(trace.len(), 1);
+        assert_eq!((trace[0].type_name, trace[0].index), ("u32", Some(3)));
+    }
+
+    #[test]
+    fn failed_reads_outside_of_a_call_are_not_kept() {
+        assert!(read_u32(&[1, 2], 0).is_err());
+        let (result, trace) = trace_reads(|| read_u32(&[1, 2, 3, 4], 0));
+        assert_eq!(result.unwrap(), 0x0403_0201);
+        assert!(trace.is_empty());
+        assert!(DECODE_TRACE.with_borrow(Option::is_none));
+    }
+
+    #[test]
+    fn nested_calls_keep_their_own_trace() {
+        let ((inner, outer_result), trace) = trace_reads(|| {
+            let (_, inner) = trace_reads(|| read_u32(&[], 1));
+            (inner, read_u32(&[], 2))
+        });
+        assert!(outer_result.is_err());
+        assert_eq!(inner.len(), 1);
+        assert_eq!(inner[0].index, Some(1));
+        assert_eq!(trace.len(), 1);
+        assert_eq!(trace[0].index, Some(2));
+    }
+
+    /// Writes a number of bytes, failing like a nested write or a write at the end of the
+    /// buffer when they don't fit.
+    #[derive(Clone, Copy)]
+    struct Filler {
+        len: usize,
+        bad_offset: bool,
+    }
+
+    impl TryWrite for Filler {
+        fn try_write(self, bytes: &mut [u8], _: ()) -> byte::Result<usize> {
+            let Some(dest) = bytes.get_mut(..self.len) else {
+                return Err(if self.bad_offset {
+                    byte::Error::BadOffset(bytes.len())
+                } else {
+                    byte::Error::Incomplete
+                });
+            };
+            dest.fill(1);
+            Ok(self.len)
+        }
+    }
+
+    #[test]
+    fn buffers_grow_until_the_value_fits() {
+        for bad_offset in [false, true] {
+            let filler = Filler {
+                len: 10_000,
+                bad_offset,
+            };
+            assert_eq!(write_to_vec(filler, ()).unwrap(), vec![1; 10_000]);
+        }
+        let empty = Filler {
+            len: 0,
+            bad_offset: false,
+        };
+        assert!(write_to_vec(empty, ()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn buffers_stop_growing_at_the_limit() {
+        let fitting = Filler {
+            len: 5000,
+            bad_offset: false,
+        };
+        assert_eq!(write_to_vec_within(fitting, (), 5000).unwrap().len(), 5000);
+
+        let too_large = Filler {
+            len: 5001,
+            bad_offset: true,
+        };
+        let result = write_to_vec_within(too_large, (), 5000);
+        assert!(matches!(result, Err(byte::Error::BadOffset(5000))));
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::bail;
+use vakfu::assets::{
+    self, DecodeError, FormatVersion, LightCell, MapChunk, MapSpriteLibrary, Tgam, parse_chunk_name,
+};
+use vakfu::game::GameMaps;
+
+type Archive = zip::ZipArchive<BufReader<File>>;
+
+/// The number of IDs listed in a problem before the rest is summarized.
+const LISTED_IDS: usize = 10;
+
+/// Checks that every archive of the game decodes and that the references between them resolve.
+///
+/// Problems are printed as they are found and make the command fail at the end, while
+/// warnings, such as unread bytes after the decoded data, are only printed.
+pub fn validate(maps: &GameMaps) -> anyhow::Result<()> {
+    let mut report = Report::default();
+
+    let library = validate_library(maps, &mut report);
+    let textures = validate_textures(maps, &mut report);
+    if let (Some(library), Some(textures)) = (&library, &textures) {
+        check_texture_references(maps, library, textures, &mut report);
+    }
+    for map_id in maps.map_ids()? {
+        validate_map(maps, map_id, library.as_ref(), &mut report);
+    }
+    validate_light_maps(maps, &mut report)?;
+
+    println!(
+        "checked {} files and {} entries: {} problems, {} warnings",
+        report.files, report.entries, report.problems, report.warnings
+    );
+    if report.problems > 0 {
+        bail!("the game data has {} problems", report.problems);
+    }
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+struct Report {
+    files: usize,
+    entries: usize,
+    problems: usize,
+    warnings: usize,
+}
+
+impl Report {
+    fn problem(&mut self, file: &Path, entry: Option<&str>, message: impl Display) {
+        self.problems += 1;
+        println!("error: {}: {message}", location(file, entry));
+    }
+
+    fn warning(&mut self, file: &Path, entry: Option<&str>, message: impl Display) {
+        self.warnings += 1;
+        println!("warning: {}: {message}", location(file, entry));
+    }
+}
+
+fn location(file: &Path, entry: Option<&str>) -> String {
+    match entry {
+        Some(entry) => format!("{} [{entry}]", file.display()),
+        None => file.display().to_string(),
+    }
+}
+
+fn validate_library(maps: &GameMaps, report: &mut Report) -> Option<MapSpriteLibrary> {
+    let path = maps.library_path();
+    let mut archive = open_archive(&path, report)?;
+    let Some(index) = archive.index_for_name("elements.lib") else {
+        report.problem(&path, None, "missing elements.lib");
+        return None;
+    };
+    let (name, bytes) = read_entry(&mut archive, index, &path, report)?;
+    let decoded =
+        FormatVersion::decode_entry::<MapSpriteLibrary>(&bytes, &name, maps.format_version())
+            .map(|(library, read, _)| (library, read));
+    check_decoded(decoded, bytes.len(), &path, &name, report)
+}
+
+/// Decodes every texture, returning the IDs of the textures present in the archive.
+fn validate_textures(maps: &GameMaps, report: &mut Report) -> Option<BTreeSet<i32>> {
+    let path = maps.textures_path();
+    let mut archive = open_archive(&path, report)?;
+    let mut textures = BTreeSet::new();
+    for index in 0..archive.len() {
+        let Some((name, bytes)) = read_entry(&mut archive, index, &path, report) else {
+            continue;
+        };
+        let texture_id = name
+            .strip_prefix("gfx/")
+            .and_then(|name| name.strip_suffix(".tgam"))
+            .and_then(|id| id.parse().ok());
+        let Some(texture_id) = texture_id else {
+            continue;
+        };
+        let decoded = assets::decode::<Tgam<'_>>(&bytes, &name);
+        if check_decoded(decoded, bytes.len(), &path, &name, report).is_some() {
+            textures.insert(texture_id);
+        }
+    }
+    Some(textures)
+}
+
+fn check_texture_references(
+    maps: &GameMaps,
+    library: &MapSpriteLibrary,
+    textures: &BTreeSet<i32>,
+    report: &mut Report,
+) {
+    let mut missing: BTreeMap<i32, BTreeSet<i32>> = BTreeMap::new();
+    for def in library.iter() {
+        if !textures.contains(&def.texture_id()) {
+            missing
+                .entry(def.texture_id())
+                .or_default()
+                .insert(def.id());
+        }
+    }
+    for (texture_id, definitions) in missing {
+        report.problem(
+            &maps.library_path(),
+            Some("elements.lib"),
+            format_args!(
+                "texture {texture_id} is missing from gfx.jar, used by definitions {}",
+                id_list(&definitions)
+            ),
+        );
+    }
+}
+
+fn validate_map(
+    maps: &GameMaps,
+    map_id: i32,
+    library: Option<&MapSpriteLibrary>,
+    report: &mut Report,
+) {
+    let path = maps.map_path(map_id);
+    let Some(mut archive) = open_archive(&path, report) else {
+        return;
+    };
+    let mut missing_definitions = BTreeSet::new();
+    for index in 0..archive.len() {
+        let Some((name, bytes)) = read_entry(&mut archive, index, &path, report) else {
+            continue;
+        };
+        if parse_chunk_name(&name).is_none() {
+            continue;
+        }
+        let decoded = assets::decode::<MapChunk>(&bytes, &name);
+        let Some(chunk) = check_decoded(decoded, bytes.len(), &path, &name, report) else {
+            continue;
+        };
+
+        let mut bad_indices = 0;
+        for elem in chunk.elements() {
+            if elem.try_group().is_none() || elem.try_color().is_none() {
+                bad_indices += 1;
+            }
+            if library.is_some_and(|library| library.get(elem.definition_id()).is_none()) {
+                missing_definitions.insert(elem.definition_id());
+            }
+        }
+        if bad_indices > 0 {
+            report.problem(
+                &path,
+                Some(&name),
+                format_args!("{bad_indices} elements refer to a missing group or color"),
+            );
+        }
+    }
+    if !missing_definitions.is_empty() {
+        report.problem(
+            &path,
+            None,
+            format_args!(
+                "elements refer to definitions missing from the library: {}",
+                id_list(&missing_definitions)
+            ),
+        );
+    }
+}
+
+fn validate_light_maps(maps: &GameMaps, report: &mut Report) -> anyhow::Result<()> {
+    let dir = maps.path().join("light");
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    let mut paths: Vec<PathBuf> = fs::read_dir(&dir)?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension() == Some("jar".as_ref()))
+        .collect();
+    paths.sort_unstable();
+
+    for path in paths {
+        let Some(mut archive) = open_archive(&path, report) else {
+            continue;
+        };
+        for index in 0..archive.len() {
+            let Some((name, bytes)) = read_entry(&mut archive, index, &path, report) else {
+                continue;
+            };
+            if parse_chunk_name(&name).is_some() {
+                let decoded = assets::decode::<LightCell>(&bytes, &name);
+                check_decoded(decoded, bytes.len(), &path, &name, report);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn open_archive(path: &Path, report: &mut Report) -> Option<Archive> {
+    report.files += 1;
+    let archive = File::open(path)
+        .map_err(zip::result::ZipError::from)
+        .and_then(|file| zip::ZipArchive::new(BufReader::new(file)));
+    match archive {
+        Ok(archive) => Some(archive),
+        Err(err) => {
+            report.problem(
+                path,
+                None,
+                format_args!("could not open the archive: {err}"),
+            );
+            None
+        }
+    }
+}
+
+/// Reads an entry of an archive, returning its name and content, or `None` for directories
+/// and entries that could not be read.
+fn read_entry(
+    archive: &mut Archive,
+    index: usize,
+    path: &Path,
+    report: &mut Report,
+) -> Option<(String, Vec<u8>)> {
+    let mut entry = match archive.by_index(index) {
+        Ok(entry) => entry,
+        Err(err) => {
+            let entry = format!("entry {index}");
+            report.problem(path, Some(&entry), format_args!("could not read: {err}"));
+            return None;
+        }
+    };
+    if entry.is_dir() {
+        return None;
+    }
+    report.entries += 1;
+    let name = entry.name().to_owned();
+    let mut bytes = Vec::with_capacity(entry.size() as usize);
+    if let Err(err) = entry.read_to_end(&mut bytes) {
+        report.problem(path, Some(&name), format_args!("could not read: {err}"));
+        return None;
+    }
+    Some((name, bytes))
+}
+
+/// Reports where the decoding of an entry failed and whether bytes were left unread.
+fn check_decoded<T>(
+    decoded: Result<(T, usize), DecodeError>,
+    len: usize,
+    path: &Path,
+    entry: &str,
+    report: &mut Report,
+) -> Option<T> {
+    match decoded {
+        Ok((value, read)) => {
+            if read < len {
+                report.warning(
+                    path,
+                    Some(entry),
+                    format_args!(
+                        "{} bytes left after the decoded data, at byte {read}",
+                        len - read
+                    ),
+                );
+            }
+            Some(value)
+        }
+        Err(err) => {
+            report.problem(path, Some(entry), err);
+            None
+        }
+    }
+}
+
+/// Formats a list of IDs, summarizing the ones past [`LISTED_IDS`].
+fn id_list(ids: &BTreeSet<i32>) -> String {
+    let listed: Vec<_> = ids.iter().take(LISTED_IDS).map(i32::to_string).collect();
+    if ids.len() > LISTED_IDS {
+        format!("{} and {} more", listed.join(", "), ids.len() - LISTED_IDS)
+    } else {
+        listed.join(", ")
+    }
+}
@@ -0,0 +1,121 @@
+//! Helpers shared by the integration tests.
+
+// every test crate compiles its own copy of the module and only uses some of the helpers
+#![allow(dead_code)]
+
+use std::io::{Cursor, Read, Write};
+
+use zip::write::SimpleFileOptions;
+
+/// Writes a zip archive holding the given entries, in order.
+pub fn archive(entries: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, bytes) in entries {
+        writer
+            .start_file(*name, SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(bytes).unwrap();
+    }
+    writer.finish().unwrap().into_inner()
+}
+
+/// Reads an entry of a zip archive.
+pub fn read_entry(archive: Vec<u8>, name: &str) -> Vec<u8> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(archive)).unwrap();
+    let mut entry = archive.by_name(name).unwrap();
+    let mut bytes = Vec::new();
+    entry.read_to_end(&mut bytes).unwrap();
+    bytes
+}
+
+/// Encodes a chunk at the given cell origin with two groups, every kind of color and a 2x2
+/// sub-chunk with a varying number of elements per cell.
+pub fn chunk_bytes(map_x: i32, map_y: i32) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    // bounds
+    bytes.extend(0i32.to_le_bytes());
+    bytes.extend(0i32.to_le_bytes());
+    bytes.extend((-2i16).to_le_bytes());
+    bytes.extend(18i32.to_le_bytes());
+    bytes.extend(18i32.to_le_bytes());
+    bytes.extend(5i16.to_le_bytes());
+    // groups
+    bytes.extend(2u16.to_le_bytes());
+    for (key, layer, id) in [(1000i32, 0u8, 7i32), (-2001, 3, -1)] {
+        bytes.extend(key.to_le_bytes());
+        bytes.push(layer);
+        bytes.extend(id.to_le_bytes());
+    }
+    // colors
+    bytes.extend(5u16.to_le_bytes());
+    bytes.push(0x0);
+    bytes.extend([0x1, 10, 20, 30]);
+    bytes.extend([0x3, 10, 20, 30, 0x80]);
+    bytes.extend([0x5, 1, 2, 3, 4, 5, 6]);
+    bytes.extend([0x7, 1, 2, 3, 4, 5, 6, 7, 0xff]);
+    // origin
+    bytes.extend(map_x.to_le_bytes());
+    bytes.extend(map_y.to_le_bytes());
+    // sub-chunks
+    bytes.extend(1u16.to_le_bytes());
+    bytes.extend([0, 2, 0, 2]);
+    for count in 0..4u8 {
+        bytes.push(count);
+        for i in 0..count {
+            bytes.extend((i16::from(i) - 1).to_le_bytes());
+            bytes.extend([i, 2, 0]);
+            bytes.extend((1000 + i32::from(i)).to_le_bytes());
+            bytes.extend(u16::from(i % 2).to_le_bytes());
+            bytes.extend(u16::from(i).to_le_bytes());
+        }
+    }
+    bytes
+}
+
+/// Encodes a sprite definition, animated when `frames` is not empty.
+pub fn definition_bytes(id: i32, frames: &[(u16, [u16; 2])]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend(id.to_le_bytes());
+    bytes.extend((-43i16).to_le_bytes());
+    bytes.extend(120i16.to_le_bytes());
+    bytes.extend(86u16.to_le_bytes());
+    bytes.extend(130u16.to_le_bytes());
+    bytes.extend(128u16.to_le_bytes());
+    bytes.extend(256u16.to_le_bytes());
+    bytes.extend((id * 10).to_le_bytes());
+    // flags, visual height, visibility mask, export mask, shader
+    bytes.extend([0b1001_0011, 12, 1, 2, 0]);
+    bytes.push(frames.len() as u8);
+    if !frames.is_empty() {
+        let total_time: u16 = frames.iter().map(|(duration, _)| duration).sum();
+        bytes.extend(u32::from(total_time).to_le_bytes());
+        bytes.extend(64u16.to_le_bytes());
+        bytes.extend(64u16.to_le_bytes());
+        bytes.extend(128u16.to_le_bytes());
+        bytes.extend(128u16.to_le_bytes());
+        for (duration, _) in frames {
+            bytes.extend(duration.to_le_bytes());
+        }
+        for (_, [x, y]) in frames {
+            bytes.extend(x.to_le_bytes());
+            bytes.extend(y.to_le_bytes());
+        }
+    }
+    // ground sound
+    bytes.push(3);
+    bytes
+}
+
+/// Encodes a library with definitions ordered by ID, like the writer does.
+pub fn library_bytes() -> Vec<u8> {
+    let definitions = [
+        definition_bytes(-5, &[]),
+        definition_bytes(1, &[(100, [0, 0]), (150, [64, 0]), (100, [0, 64])]),
+        definition_bytes(42, &[]),
+    ];
+    let mut bytes = (definitions.len() as u32).to_le_bytes().to_vec();
+    for definition in definitions {
+        bytes.extend(definition);
+    }
+    bytes
+}
@@ -0,0 +1,203 @@
+use std::io::Cursor;
+
+use byte::BytesExt;
+use byte::ctx::LittleEndian;
+use common::archive;
+use vakfu::assets::{
+    self, Animation, Color, FormatVersion, Map, MapChunk, MapSpriteLibrary, SpriteFlags,
+};
+
+mod common;
+
+/// A library with a static definition 7 and a definition 12 animated with two frames.
+const V1_LIBRARY: &[u8] = include_bytes!("fixtures/v1/elements.lib");
+/// The chunk at `(1, -2)` with a 1x2 sub-chunk, the first cell holding an element of definition
+/// 7 and the second one elements of definitions 12 and 99.
+const V1_CHUNK: &[u8] = include_bytes!("fixtures/v1/1_-2");
+/// The definitions of the V1 library with their render size removed, as there's no sample of
+/// the hypothetical V0 layout from an older release.
+const V0_LIBRARY: &[u8] = include_bytes!("fixtures/v0/elements.lib");
+
+#[test]
+fn v1_library_is_detected() {
+    let (library, read, version) =
+        FormatVersion::detect::<MapSpriteLibrary>(V1_LIBRARY, "elements.lib").unwrap();
+    assert_eq!(version, FormatVersion::V1);
+    assert_eq!(read, V1_LIBRARY.len());
+    assert_eq!(library.iter().len(), 2);
+
+    let still = library.get(7).unwrap();
+    assert_eq!(still.origin(), (-43, 120));
+    assert_eq!(still.texture_id(), 70);
+    assert_eq!(still.flags(), SpriteFlags::new().with_is_walkable(true));
+    assert_eq!(still.animation(), Animation::None);
+    assert_eq!(still.ground_sound(), 1);
+
+    let animated = library.get(12).unwrap();
+    assert_eq!(animated.texture_size(), (64, 64));
+    assert_eq!(animated.size(), (128, 64));
+    assert_eq!(animated.flags().slope(), 2);
+    assert!(animated.flags().is_flip());
+    assert_eq!(animated.ground_sound(), 3);
+    let Animation::Frames(frames) = animated.animation() else {
+        panic!("definition 12 is animated");
+    };
+    assert_eq!(frames.total_time(), 250);
+    assert_eq!(frames.frame_durations(), [100, 150]);
+    assert_eq!(
+        frames
+            .iter()
+            .map(|frame| (frame.x, frame.y))
+            .collect::<Vec<_>>(),
+        [(0, 0), (64, 0)]
+    );
+}
+
+#[test]
+fn v1_library_loads_from_an_archive() {
+    let library =
+        MapSpriteLibrary::load(Cursor::new(archive(&[("elements.lib", V1_LIBRARY)]))).unwrap();
+    assert!(library.get(7).is_some());
+    assert!(library.get(12).is_some());
+}
+
+#[test]
+fn chunk_decodes() {
+    let (chunk, read) = assets::decode::<MapChunk>(V1_CHUNK, "1_-2").unwrap();
+    assert_eq!(read, V1_CHUNK.len());
+
+    assert_eq!(chunk.coords(), (1, -2));
+    assert_eq!(chunk.min(), (18, -36, -1));
+    assert_eq!(chunk.max(), (36, -18, 4));
+    assert_eq!(chunk.groups().len(), 1);
+    assert_eq!(chunk.colors()[0], Color::None);
+    assert!(matches!(chunk.colors()[1], Color::Rgb(_)));
+
+    let elements: Vec<_> = chunk
+        .elements()
+        .map(|elem| {
+            (
+                elem.cell_x(),
+                elem.cell_y(),
+                elem.cell_z(),
+                elem.definition_id(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        elements,
+        [(18, -36, 0, 7), (18, -35, -1, 12), (18, -35, 3, 99)]
+    );
+    assert!(chunk.elements().all(|elem| elem.group().id() == 5));
+}
+
+#[test]
+fn chunk_loads_from_an_archive() {
+    let map = Map::load(Cursor::new(archive(&[("1_-2", V1_CHUNK)]))).unwrap();
+    assert_eq!(map.chunks().len(), 1);
+    assert_eq!(map.chunks()[0].elements().count(), 3);
+}
+
+#[test]
+fn trailing_bytes_are_left_unread() {
+    let mut bytes = V1_CHUNK.to_vec();
+    bytes.extend([0xAB; 3]);
+    let (_, read) = assets::decode::<MapChunk>(&bytes, "1_-2").unwrap();
+    assert_eq!(read, V1_CHUNK.len());
+}
+
+#[test]
+fn truncated_library_reports_the_failing_definition() {
+    let truncated = &V1_LIBRARY[..V1_LIBRARY.len() - 4];
+    let Err(err) = FormatVersion::detect::<MapSpriteLibrary>(truncated, "elements.lib") else {
+        panic!("the library is truncated");
+    };
+    assert_eq!(err.entry(), "elements.lib");
+
+    let path: Vec<_> = err.path().iter().map(ToString::to_string).collect();
+    assert_eq!(
+        path,
+        ["MapSpriteLibrary", "MapSpriteDefinition[1]", "Frames"]
+    );
+    let definition = &err.path()[1];
+    assert_eq!(definition.offset, 4 + 27);
+}
+
+#[test]
+fn truncated_chunk_reports_the_failing_cell() {
+    let truncated = &V1_CHUNK[..V1_CHUNK.len() - 1];
+    let Err(err) = assets::decode::<MapChunk>(truncated, "1_-2") else {
+        panic!("the chunk is truncated");
+    };
+
+    let path: Vec<_> = err.path().iter().map(ToString::to_string).collect();
+    assert_eq!(
+        path,
+        ["MapChunk", "MapSubChunk[0]", "MapCell[1]", "MapSrite[1]"]
+    );
+    assert_eq!(
+        err.innermost().map(|step| step.offset),
+        Some(V1_CHUNK.len() - 13)
+    );
+}
+
+#[test]
+fn v0_library_is_detected() {
+    let (library, read, version) =
+        FormatVersion::detect::<MapSpriteLibrary>(V0_LIBRARY, "elements.lib").unwrap();
+    assert_eq!(version, FormatVersion::V0);
+    assert_eq!(read, V0_LIBRARY.len());
+
+    let (v1, _, _) = FormatVersion::detect::<MapSpriteLibrary>(V1_LIBRARY, "elements.lib").unwrap();
+    for id in [7, 12] {
+        let (old, new) = (library.get(id).unwrap(), v1.get(id).unwrap());
+        assert_eq!(old.size(), old.texture_size());
+        assert_eq!(old.texture_size(), new.texture_size());
+        assert_eq!(old.texture_id(), new.texture_id());
+        assert_eq!(old.flags(), new.flags());
+        assert_eq!(old.animation(), new.animation());
+        assert_eq!(old.ground_sound(), new.ground_sound());
+    }
+}
+
+#[test]
+fn forced_version_is_used_over_the_detected_one() {
+    let (_, read, version) =
+        FormatVersion::decode_entry::<MapSpriteLibrary>(V0_LIBRARY, "elements.lib", None).unwrap();
+    assert_eq!((read, version), (V0_LIBRARY.len(), FormatVersion::V0));
+
+    // the V0 library only decodes partially with the V1 layout
+    let (_, read, version) = FormatVersion::decode_entry::<MapSpriteLibrary>(
+        V0_LIBRARY,
+        "elements.lib",
+        Some(FormatVersion::V1),
+    )
+    .unwrap();
+    assert_eq!(version, FormatVersion::V1);
+    assert!(read < V0_LIBRARY.len());
+
+    let archive = archive(&[("elements.lib", V0_LIBRARY)]);
+    let library =
+        MapSpriteLibrary::load_with_version(Cursor::new(&archive), Some(FormatVersion::V0))
+            .unwrap();
+    assert_eq!(library.get(12).unwrap().size(), (64, 64));
+}
+
+#[test]
+fn library_reads_the_v1_layout_with_an_endianness() {
+    let library: MapSpriteLibrary = V1_LIBRARY.read(&mut 0, LittleEndian).unwrap();
+    let (detected, _, _) =
+        FormatVersion::detect::<MapSpriteLibrary>(V1_LIBRARY, "elements.lib").unwrap();
+    assert_eq!(library, detected);
+}
+
+#[test]
+fn versions_parse_from_their_names() {
+    for &version in FormatVersion::ALL {
+        assert_eq!(
+            version.to_string().parse::<FormatVersion>().unwrap(),
+            version
+        );
+    }
+    assert!("v2".parse::<FormatVersion>().is_err());
+}
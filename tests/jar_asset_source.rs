@@ -0,0 +1,80 @@
+#![cfg(feature = "bevy")]
+
+use std::fs;
+use std::io::{Cursor, Write};
+use std::path::PathBuf;
+use std::thread;
+
+use vakfu::assets::JarAssetSource;
+use zip::CompressionMethod;
+use zip::write::SimpleFileOptions;
+
+const THREADS: usize = 8;
+const ROUNDS: usize = 16;
+
+/// Generates distinct, compressible contents for the entries of the archive.
+fn entries() -> Vec<(String, Vec<u8>)> {
+    (0..12)
+        .map(|i| {
+            let bytes = (0..4096 + i * 511)
+                .map(|j| (j / (i + 1) % 251) as u8)
+                .collect();
+            (format!("gfx/{i}.tgam"), bytes)
+        })
+        .collect()
+}
+
+/// Writes a zip archive with both stored and deflated entries to the temporary directory.
+fn write_archive(name: &str, entries: &[(String, Vec<u8>)]) -> PathBuf {
+    let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    for (i, (name, bytes)) in entries.iter().enumerate() {
+        let method = if i % 2 == 0 {
+            CompressionMethod::Deflated
+        } else {
+            CompressionMethod::Stored
+        };
+        let options = SimpleFileOptions::default().compression_method(method);
+        writer.start_file(name.as_str(), options).unwrap();
+        writer.write_all(bytes).unwrap();
+    }
+    let path = std::env::temp_dir().join(format!("vakfu-{}-{name}.jar", std::process::id()));
+    fs::write(&path, writer.finish().unwrap().into_inner()).unwrap();
+    path
+}
+
+#[test]
+fn entries_read_from_multiple_threads_match() {
+    let entries = entries();
+    let path = write_archive("concurrent", &entries);
+    let source = JarAssetSource::new(&path).unwrap();
+
+    thread::scope(|scope| {
+        for offset in 0..THREADS {
+            let (source, entries) = (&source, &entries);
+            scope.spawn(move || {
+                // every thread goes through the entries in a different order
+                for i in 0..ROUNDS * entries.len() {
+                    let (name, bytes) = &entries[(i + offset) % entries.len()];
+                    assert_eq!(&source.read_entry(name).unwrap(), bytes, "{name}");
+                }
+            });
+        }
+    });
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn clones_read_the_same_entries() {
+    let entries = entries();
+    let path = write_archive("clones", &entries);
+    let source = JarAssetSource::new(&path).unwrap();
+
+    thread::scope(|scope| {
+        for (name, bytes) in &entries {
+            let source = source.clone();
+            scope.spawn(move || assert_eq!(&source.read_entry(name).unwrap(), bytes, "{name}"));
+        }
+    });
+    assert!(source.read_entry("gfx/missing.tgam").is_err());
+    fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,37 @@
+use std::io::Cursor;
+
+use common::{archive, chunk_bytes, read_entry};
+use vakfu::assets::Map;
+
+mod common;
+
+fn save(map: &Map) -> Vec<u8> {
+    let mut output = Cursor::new(Vec::new());
+    map.save(&mut output).unwrap();
+    output.into_inner()
+}
+
+#[test]
+fn load_save_load_yields_identical_chunks() {
+    let first = chunk_bytes(18, -36);
+    let second = chunk_bytes(0, 0);
+    let input = archive(&[
+        ("1_-2", &first),
+        ("META-INF/MANIFEST.MF", b"Manifest-Version: 1.0\n"),
+        ("0_0", &second),
+    ]);
+
+    let map = Map::load(Cursor::new(input)).unwrap();
+    assert_eq!(map.chunks().len(), 2);
+
+    let reloaded = Map::load(Cursor::new(save(&map))).unwrap();
+    assert_eq!(map, reloaded);
+}
+
+#[test]
+fn saved_chunks_match_the_original_encoding() {
+    let original = chunk_bytes(18, -36);
+    let map = Map::load(Cursor::new(archive(&[("1_-2", &original)]))).unwrap();
+
+    assert_eq!(read_entry(save(&map), "1_-2"), original);
+}
@@ -0,0 +1,47 @@
+use std::io::Cursor;
+
+use common::{archive, library_bytes, read_entry};
+use vakfu::assets::{Animation, MapSpriteLibrary};
+
+mod common;
+
+fn data_archive(elements: &[u8]) -> Vec<u8> {
+    archive(&[("elements.lib", elements)])
+}
+
+fn save(library: &MapSpriteLibrary) -> Vec<u8> {
+    let mut output = Cursor::new(Vec::new());
+    library.save(&mut output).unwrap();
+    output.into_inner()
+}
+
+#[test]
+fn load_save_load_yields_identical_definitions() {
+    let library = MapSpriteLibrary::load(Cursor::new(data_archive(&library_bytes()))).unwrap();
+    assert_eq!(library.iter().len(), 3);
+    assert!(matches!(library[1].animation(), Animation::Frames(_)));
+
+    let reloaded = MapSpriteLibrary::load(Cursor::new(save(&library))).unwrap();
+    assert_eq!(library, reloaded);
+}
+
+#[test]
+fn saved_library_matches_the_original_encoding() {
+    let original = library_bytes();
+    let library = MapSpriteLibrary::load(Cursor::new(data_archive(&original))).unwrap();
+
+    assert_eq!(read_entry(save(&library), "elements.lib"), original);
+}
+
+#[test]
+fn edited_library_round_trips() {
+    let mut library = MapSpriteLibrary::load(Cursor::new(data_archive(&library_bytes()))).unwrap();
+    let removed = library.remove(42).unwrap();
+    assert!(library.get(42).is_none());
+
+    let mut other = MapSpriteLibrary::default();
+    other.insert(removed);
+    let reloaded = MapSpriteLibrary::load(Cursor::new(save(&other))).unwrap();
+    assert_eq!(reloaded.iter().len(), 1);
+    assert_eq!(reloaded.get(42).map(|def| def.texture_id()), Some(420));
+}